@@ -4,15 +4,13 @@ use crossterm::{
     event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
     terminal::{disable_raw_mode, enable_raw_mode},
 };
-use fuzzy_matcher::FuzzyMatcher;
-use fuzzy_matcher::skim::SkimMatcherV2;
 use ratatui::{
     Frame, Terminal,
     backend::CrosstermBackend,
     crossterm::terminal,
     layout::{Constraint, Direction, Layout, Rect},
     prelude::Backend,
-    style::{Color, Style},
+    style::{Color, Modifier, Style},
     text::{Line, Span, Text},
     widgets::{Block, Borders, Clear, List, ListState, Paragraph, Wrap},
 };
@@ -22,13 +20,15 @@ use ratatui_image::{
     protocol::StatefulProtocol,
 };
 use regex::Regex;
+use std::collections::HashMap;
 use std::{
     env,
     fs::{self, File},
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, Read},
     path::{Path, PathBuf},
     pin::Pin,
     process::Command,
+    sync::Arc,
     time::{SystemTime, UNIX_EPOCH},
 };
 use syntect::{
@@ -38,7 +38,7 @@ use syntect::{
 };
 
 // INTERNAL MODULES
-use crate::{node_info::NodeInfo, node_info::NodeType};
+use crate::{node_info::NodeInfo, node_info::NodeStat, node_info::NodeType};
 
 const APP_NAME: &str = "sonar";
 
@@ -80,6 +80,24 @@ const SEP: &str = "────────────────────
 
 const ASK: &str = "{ASK}";
 
+// How many directories the session history keeps
+const MAX_HISTORY: usize = 100;
+
+// How many names the search highlights are cached for
+const MAX_MATCH_HITS: usize = 10_000;
+
+// How much of a file inside an archive is read for its preview
+const MEMBER_PREVIEW_BYTES: u64 = 256 * 1024;
+
+// Lines shown above a content search match in its preview
+const MATCH_CONTEXT: usize = 5;
+
+// How much of a file is checked when detecting binary content
+const BINARY_SNIFF_BYTES: usize = 8 * 1024;
+
+// Upper bound for the hex dump when the preview has no limit
+const HEX_PREVIEW_MAX: usize = 64 * 1024;
+
 // Nerd font icons
 mod nf {
     pub const MAG: &str = "󰍉";
@@ -99,6 +117,7 @@ mod nf {
     pub const DUDE: &str = "󰢚";
     pub const WAIT: &str = "󱑆";
     pub const EYEN: &str = "󰈉";
+    pub const RIGHT: &str = "→";
     // UNUSED
     // pub const B4: &str = "█";
     // pub const B3: &str = "▓";
@@ -147,14 +166,22 @@ mod log {
 }
 
 mod node_meta {
-    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
     use std::{path::PathBuf, time::SystemTime};
 
     #[derive(Clone, Debug)]
     pub struct NodeMeta {
         pub size: u64,
-        pub modified: u32,
+        pub modified: Option<SystemTime>,
+        pub accessed: Option<SystemTime>,
+        pub created: Option<SystemTime>, // Not every filesystem records it
         pub permissions: u32,
+        pub uid: u32,
+        pub gid: u32,
+        pub inode: u64,
+        pub nlink: u64,
+        pub link_target: Option<PathBuf>, // Where a symlink points
+        pub items: Option<usize>,         // Entries of a directory, set by the listing
         pub mime: String,
         pub path: PathBuf,
     }
@@ -162,46 +189,64 @@ mod node_meta {
         pub fn empty() -> Self {
             NodeMeta {
                 size: 0,
-                modified: 0,
+                modified: None,
+                accessed: None,
+                created: None,
                 permissions: 0,
+                uid: 0,
+                gid: 0,
+                inode: 0,
+                nlink: 0,
+                link_target: None,
+                items: None,
                 mime: "unknown".to_string(),
                 path: PathBuf::new(),
             }
         }
         pub fn get(path: &PathBuf) -> Self {
-            match std::fs::metadata(&path) {
+            let link_target = std::fs::read_link(path).ok();
+            // Broken symlinks describe the link itself
+            let metadata = std::fs::metadata(path).or_else(|_| std::fs::symlink_metadata(path));
+            match metadata {
                 Ok(metadata) => {
-                    let size = metadata.len();
-                    let modified = metadata
-                        .modified()
-                        .ok()
-                        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
-                        .map(|duration| duration.as_secs() as u32)
-                        .unwrap_or(0);
-                    let permissions = metadata.permissions().mode();
-                    let mime = if metadata.is_file() {
+                    let file_type = metadata.file_type();
+                    let mime = if file_type.is_file() {
                         mime_guess::from_path(&path)
                             .first_raw()
                             .unwrap_or("application/octet-stream")
-                            .to_string()
+                    } else if file_type.is_dir() {
+                        "inode/directory"
+                    } else if file_type.is_symlink() {
+                        "inode/symlink"
+                    } else if file_type.is_fifo() {
+                        "inode/fifo"
+                    } else if file_type.is_socket() {
+                        "inode/socket"
+                    } else if file_type.is_block_device() {
+                        "inode/blockdevice"
                     } else {
-                        "inode/directory".to_string()
-                    };
-                    let full_path = path.clone();
+                        "inode/chardevice"
+                    }
+                    .to_string();
                     NodeMeta {
-                        size,
-                        modified,
-                        permissions,
+                        size: metadata.len(),
+                        modified: metadata.modified().ok(),
+                        accessed: metadata.accessed().ok(),
+                        created: metadata.created().ok(),
+                        permissions: metadata.permissions().mode(),
+                        uid: metadata.uid(),
+                        gid: metadata.gid(),
+                        inode: metadata.ino(),
+                        nlink: metadata.nlink(),
+                        link_target,
+                        items: None,
                         mime,
-                        path: full_path,
+                        path: path.clone(),
                     }
                 }
                 Err(_) => NodeMeta {
-                    size: 0,
-                    modified: 0,
-                    permissions: 0,
-                    mime: "unknown".to_string(),
                     path: path.clone(),
+                    ..NodeMeta::empty()
                 },
             }
         }
@@ -213,17 +258,24 @@ mod aq {
 
     use ratatui::text::Text;
     use ratatui_image::protocol::StatefulProtocol;
-    use tokio::task::JoinHandle;
+    use std::{
+        path::Path,
+        pin::Pin,
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+    use tokio::{sync::watch, task::JoinHandle};
 
-    use crate::{node_info::NodeInfo, node_meta::NodeMeta};
+    use crate::{journal, node_info::NodeInfo, node_meta::NodeMeta};
 
-    #[derive(PartialEq, Debug)]
+    #[derive(PartialEq, Debug, Clone)]
     pub enum Kind {
         ListingResult,
         ListingDir,
         ListingPreview,
         ImagePreview,
         FilePreview,
+        ContentSearch,
         FsOperation,
     }
     // Holds the data that the async fns can return
@@ -236,6 +288,8 @@ mod aq {
         pub data_image: Option<StatefulProtocol>,
         pub data_meta: Option<NodeMeta>,
         pub data_file: Option<Text<'static>>,
+        pub data_ops: Option<Vec<journal::Op>>,
+        pub data_replayed: Option<journal::Replayed>,
     }
     impl ResData {
         pub fn as_str(rc: u32, data: String) -> Self {
@@ -246,6 +300,8 @@ mod aq {
                 data_image: None,
                 data_meta: None,
                 data_file: None,
+                data_ops: None,
+                data_replayed: None,
             }
         }
         pub fn as_listing(rc: u32, data: Vec<NodeInfo>, meta: NodeMeta) -> Self {
@@ -256,6 +312,8 @@ mod aq {
                 data_image: None,
                 data_meta: Some(meta),
                 data_file: None,
+                data_ops: None,
+                data_replayed: None,
             }
         }
         pub fn as_image(rc: u32, data: StatefulProtocol, meta: NodeMeta) -> Self {
@@ -266,6 +324,8 @@ mod aq {
                 data_image: Some(data),
                 data_meta: Some(meta),
                 data_file: None,
+                data_ops: None,
+                data_replayed: None,
             }
         }
         pub fn as_file(rc: u32, data: Text<'static>, meta: NodeMeta) -> Self {
@@ -276,6 +336,33 @@ mod aq {
                 data_image: None,
                 data_meta: Some(meta),
                 data_file: Some(data),
+                data_ops: None,
+                data_replayed: None,
+            }
+        }
+        // A message plus journal ops to record for undo
+        pub fn as_ops(rc: u32, data: String, ops: Vec<journal::Op>) -> Self {
+            ResData {
+                rc,
+                data_str: Some(data),
+                data_listing: None,
+                data_image: None,
+                data_meta: None,
+                data_file: None,
+                data_ops: Some(ops),
+                data_replayed: None,
+            }
+        }
+        // The outcome of an undo or redo task
+        pub fn as_replayed(
+            rc: u32,
+            data: String,
+            ops: Vec<journal::Op>,
+            replayed: journal::Replayed,
+        ) -> Self {
+            ResData {
+                data_replayed: Some(replayed),
+                ..ResData::as_ops(rc, data, ops)
             }
         }
     }
@@ -285,21 +372,164 @@ mod aq {
         pub kind: Kind,
         pub res: ResData,
     }
+    // Progress of a long running task
+    #[derive(Clone, Default, Debug)]
+    pub struct Progress {
+        pub bytes_done: u64,
+        pub bytes_total: u64,
+        pub files_done: u64,
+        pub files_total: u64,
+        pub current: String, // File currently being worked on
+    }
+    impl Progress {
+        // Byte based if sizes are known, otherwise file based
+        // None while nothing has been totalled
+        pub fn percent(&self) -> Option<u16> {
+            let (done, total) = if self.bytes_total > 0 {
+                (self.bytes_done, self.bytes_total)
+            } else {
+                (self.files_done, self.files_total)
+            };
+            if total == 0 {
+                return None;
+            }
+            Some((done.min(total) * 100 / total) as u16)
+        }
+
+        pub fn add(&mut self, other: &Progress) {
+            self.bytes_done += other.bytes_done;
+            self.bytes_total += other.bytes_total;
+            self.files_done += other.files_done;
+            self.files_total += other.files_total;
+            if !other.current.is_empty() {
+                self.current = other.current.clone();
+            }
+        }
+    }
+
+    // Set by the queue, seen by the task at its next checkpoint
+    #[derive(Clone, Copy, PartialEq)]
+    pub enum State {
+        Running,
+        Paused,
+        Cancelled,
+    }
+
+    // Sending half of a progress channel, handed to the task
+    pub struct Reporter {
+        tx: watch::Sender<Progress>,
+        state: watch::Receiver<State>,
+    }
+    impl Reporter {
+        // A reporter nobody listens to
+        pub fn none() -> Self {
+            Reporter {
+                tx: watch::channel(Progress::default()).0,
+                state: watch::channel(State::Running).1,
+            }
+        }
+        pub fn add_total(&self, files: u64, bytes: u64) {
+            self.tx.send_modify(|p| {
+                p.files_total += files;
+                p.bytes_total += bytes;
+            });
+        }
+        pub fn start_file(&self, path: &Path) {
+            let name = path.to_string_lossy().to_string();
+            self.tx.send_modify(|p| p.current = name);
+        }
+        pub fn add_bytes(&self, bytes: u64) {
+            self.tx.send_modify(|p| p.bytes_done += bytes);
+        }
+        pub fn finish_file(&self) {
+            self.tx.send_modify(|p| p.files_done += 1);
+        }
+        // Wait here while the job is paused
+        // Returns an error once the job has been cancelled, so the task
+        // stops through its own error path and can clean up after itself
+        pub async fn checkpoint(&self) -> std::io::Result<()> {
+            let mut state = self.state.clone();
+            let state = state.wait_for(|state| *state != State::Paused).await;
+            match state.map(|state| *state) {
+                Ok(State::Cancelled) => Err(std::io::Error::other("Cancelled")),
+                _ => Ok(()),
+            }
+        }
+        // For blocking code
+        pub fn checkpoint_blocking(&self) -> std::io::Result<()> {
+            tokio::runtime::Handle::current().block_on(self.checkpoint())
+        }
+    }
+
+    // A job that can be started again with a fresh Reporter
+    type TaskFuture = Pin<Box<dyn Future<Output = ResData> + Send>>;
+    type TaskFn = Arc<dyn Fn(Reporter) -> TaskFuture + Send + Sync>;
+    // Queues a job again through the command which built it
+    pub type Requeue = Box<dyn FnOnce(&mut crate::App) + Send>;
+
+    // How a failed or cancelled job is started again
+    pub enum Retry {
+        Never,            // Only valid once, like undo
+        Rerun(TaskFn),    // Does not depend on anything checked when it was queued
+        Rebuild(Requeue), // Has to be checked and queued again
+    }
+
     // An item in the queue
     pub struct Item {
         pub id: usize,
         pub kind: Kind,
+        pub label: String,
+        started: Instant,
+        progress: Option<watch::Receiver<Progress>>,
+        state: Option<watch::Sender<State>>,
+        retry: Retry,
         handle: JoinHandle<ResData>,
     }
+    impl Item {
+        pub fn progress(&self) -> Option<Progress> {
+            self.progress.as_ref().map(|rx| rx.borrow().clone())
+        }
+        pub fn elapsed(&self) -> Duration {
+            self.started.elapsed()
+        }
+        pub fn is_paused(&self) -> bool {
+            self.state
+                .as_ref()
+                .is_some_and(|tx| *tx.borrow() == State::Paused)
+        }
+        fn is_cancelled(&self) -> bool {
+            self.state
+                .as_ref()
+                .is_some_and(|tx| *tx.borrow() == State::Cancelled)
+        }
+    }
+    // A labelled job which has stopped running
+    pub struct Finished {
+        pub id: usize,
+        pub kind: Kind,
+        pub label: String,
+        pub elapsed: Duration,
+        pub rc: Option<u32>, // None if cancelled
+        retry: Retry,
+    }
+    impl Finished {
+        pub fn can_retry(&self) -> bool {
+            !matches!(self.retry, Retry::Never) && self.rc != Some(0)
+        }
+    }
+    // How many finished jobs are remembered
+    const MAX_FINISHED: usize = 100;
     // The main queue struct
     pub struct Queue {
         items: Vec<Item>,
+        finished: Vec<Finished>,
         next_id: usize,
     }
     impl Queue {
         pub fn new() -> Self {
             Queue {
                 items: Vec::new(),
+                finished: Vec::new(),
                 next_id: 0,
             }
         }
@@ -311,10 +541,117 @@ mod aq {
             let handle = tokio::spawn(task);
             let id = self.next_id;
             self.next_id += 1;
-            self.items.push(Item { id, kind, handle });
+            self.items.push(Item {
+                id,
+                kind,
+                label: String::new(),
+                started: Instant::now(),
+                progress: None,
+                state: None,
+                retry: Retry::Never,
+                handle,
+            });
+            id
+        }
+
+        // Add a labelled job which reports its progress through the given Reporter
+        // The task may be called again to retry the job, see set_retry
+        pub fn add_task_progress<F, Fut>(&mut self, kind: Kind, label: String, task: F) -> usize
+        where
+            F: Fn(Reporter) -> Fut + Send + Sync + 'static,
+            Fut: std::future::Future<Output = ResData> + Send + 'static,
+        {
+            let task: TaskFn = Arc::new(move |reporter| Box::pin(task(reporter)));
+            self.spawn_job(kind, label, task)
+        }
+
+        fn spawn_job(&mut self, kind: Kind, label: String, task: TaskFn) -> usize {
+            let (tx, rx) = watch::channel(Progress::default());
+            let (state_tx, state_rx) = watch::channel(State::Running);
+            let handle = tokio::spawn(task(Reporter {
+                tx,
+                state: state_rx,
+            }));
+            let id = self.next_id;
+            self.next_id += 1;
+            self.items.push(Item {
+                id,
+                kind,
+                label,
+                started: Instant::now(),
+                progress: Some(rx),
+                state: Some(state_tx),
+                retry: Retry::Rerun(task),
+                handle,
+            });
             id
         }
 
+        // Change how a job is started again, see Retry
+        pub fn set_retry(&mut self, id: usize, retry: Retry) {
+            if let Some(item) = self.items.iter_mut().find(|item| item.id == id) {
+                item.retry = retry;
+            }
+        }
+
+        // Stop a running job, it shows up as cancelled once reaped
+        // Jobs with a reporter stop at their next checkpoint and clean up
+        // their partial output, the rest are aborted
+        pub fn cancel(&mut self, id: usize) -> bool {
+            match self.items.iter().find(|item| item.id == id) {
+                Some(item) => {
+                    match item.state.as_ref() {
+                        Some(tx) => {
+                            tx.send_replace(State::Cancelled);
+                        }
+                        None => item.handle.abort(),
+                    }
+                    true
+                }
+                None => false,
+            }
+        }
+
+        // Pause or resume a job at its next checkpoint
+        // Returns the new paused state
+        pub fn toggle_pause(&mut self, id: usize) -> Option<bool> {
+            let item = self.items.iter().find(|item| item.id == id)?;
+            let tx = item.state.as_ref()?;
+            let state = match *tx.borrow() {
+                State::Running => State::Paused,
+                State::Paused => State::Running,
+                State::Cancelled => return None,
+            };
+            tx.send_replace(state);
+            Some(state == State::Paused)
+        }
+
+        // Start a failed or cancelled job again under a new id
+        // Jobs which have to be rebuilt are handed back to the caller
+        pub fn retry(&mut self, id: usize) -> Option<Requeue> {
+            let i = self
+                .finished
+                .iter()
+                .position(|job| job.id == id && job.can_retry())?;
+            let job = self.finished.remove(i);
+            match job.retry {
+                Retry::Rerun(task) => {
+                    self.spawn_job(job.kind, job.label, task);
+                    None
+                }
+                Retry::Rebuild(requeue) => Some(requeue),
+                Retry::Never => None,
+            }
+        }
+
+        pub fn get_finished(&self) -> &Vec<Finished> {
+            &self.finished
+        }
+
+        pub fn clear_finished(&mut self) {
+            self.finished.clear();
+        }
+
         // Abort and remove any existing task with the same title
         pub fn add_task_unique<F>(&mut self, kind: Kind, task: F) -> Option<usize>
         where
@@ -347,7 +684,27 @@ mod aq {
             // Iterate in reverse to avoid shifting indices
             for &i in finished_indices.iter().rev() {
                 let item = self.items.remove(i);
-                if let Ok(result) = item.handle.await {
+                let cancelled = item.is_cancelled();
+                let result = item.handle.await;
+                // Only labelled jobs are worth remembering
+                if !item.label.is_empty() {
+                    self.finished.push(Finished {
+                        id: item.id,
+                        kind: item.kind.clone(),
+                        label: item.label.clone(),
+                        elapsed: item.started.elapsed(),
+                        rc: result
+                            .as_ref()
+                            .ok()
+                            .filter(|_| !cancelled)
+                            .map(|res| res.rc),
+                        retry: item.retry,
+                    });
+                    if self.finished.len() > MAX_FINISHED {
+                        self.finished.remove(0);
+                    }
+                }
+                if let Ok(result) = result {
                     completed.push(Res {
                         id: item.id,
                         kind: item.kind,
@@ -366,16 +723,34 @@ mod aq {
         pub fn get_pending(&self) -> Vec<&Item> {
             self.items.iter().collect()
         }
+
+        pub fn has_pending(&self, kind: &Kind) -> bool {
+            self.items.iter().any(|item| &item.kind == kind)
+        }
+
+        // Combined progress of all tasks which report it
+        pub fn progress(&self) -> Option<Progress> {
+            let mut total: Option<Progress> = None;
+            for progress in self.items.iter().filter_map(|item| item.progress()) {
+                total.get_or_insert_with(Progress::default).add(&progress);
+            }
+            total
+        }
     }
 }
 
 // Command implementations
 mod cmd {
-    use crate::{APP_NAME, App, SEP, cfg, cmd_data, cs, kb, log, sc, shell_cmds};
-    use crate::{aq, util};
+    use crate::{APP_NAME, App, SEP, Tab, cfg, cmd_data, cs, kb, log, sc, shell_cmds};
+    use crate::{aq, archive, bookmarks, conflict, frecency, fs_ops, grep, journal, matching};
+    use crate::{rename, sort, templates, trash, util};
     use clipboard::ClipboardContext;
     use clipboard::ClipboardProvider;
-    use std::{env, fs, path::PathBuf, process::Command};
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+        process::Command,
+    };
 
     pub fn exit(app: &mut App, _args: Vec<&str>) {
         app.should_quit = true;
@@ -383,6 +758,9 @@ mod cmd {
 
     pub fn enter(app: &mut App, _args: Vec<&str>) {
         // Update input to empty to reset search
+        if app.focused.is_dir() {
+            app.remember_dir();
+        }
         app.search_buf = String::new();
         app.update_results();
         // Get focused
@@ -453,12 +831,30 @@ mod cmd {
                     app.handle_cmd(&focused.name);
                     return;
                 }
+                // Open content search matches at their line
+                if focused.is_match() {
+                    edit(app, vec![]);
+                    return;
+                }
+                // Bookmarks go to their target
+                if app.mode_bookmarks {
+                    goto_bookmark(app, vec![&focused.name]);
+                    return;
+                }
                 // If we have a file, run the on_enter command
                 // We have a directory, enter it
-                if focused.is_file() {
+                // Archives are entered like directories
+                let focused_path = app.cwd.join(&focused.name);
+                let is_archive =
+                    archive::Format::from_path(&focused_path).is_some() && focused_path.is_file();
+                if focused.is_file() && archive::split(&app.cwd).is_some() {
+                    app.set_output("Archive", "Copy the file out of the archive to open it.");
+                } else if focused.is_file() && !is_archive {
                     app.handle_cmd(app.cfg.cmd_on_enter.clone().as_str());
                     return;
-                } else if focused.is_dir() {
+                } else if focused.is_dir() || is_archive {
+                    app.mode_jump = false;
+                    app.mode_history = false;
                     app.append_cwd(&app.focused.name.clone().into());
                     app.update_listing();
                     app.update_results();
@@ -487,7 +883,36 @@ mod cmd {
     }
 
     pub fn dir_back(app: &mut App, _args: Vec<&str>) {
-        app.append_cwd(&app.lwd.clone());
+        if app.history_pos == 0 {
+            app.set_output("History", "No earlier directory in this session.");
+            return;
+        }
+        app.history_pos -= 1;
+        app.change_cwd(app.history[app.history_pos].clone(), false);
+        app.update_listing();
+        app.update_results();
+        app.focus_index = 0;
+    }
+
+    pub fn dir_forward(app: &mut App, _args: Vec<&str>) {
+        if app.history_pos + 1 >= app.history.len() {
+            app.set_output("History", "No later directory in this session.");
+            return;
+        }
+        app.history_pos += 1;
+        app.change_cwd(app.history[app.history_pos].clone(), false);
+        app.update_listing();
+        app.update_results();
+        app.focus_index = 0;
+    }
+
+    // Toggle picking from the directories visited this session
+    pub fn history(app: &mut App, _args: Vec<&str>) {
+        app.mode_history = !app.mode_history;
+        app.mode_jump = false;
+        app.mode_bookmarks = false;
+        app.mode_cmd_finder = false;
+        app.search_buf = String::new();
         app.update_listing();
         app.update_results();
         app.focus_index = 0;
@@ -598,99 +1023,65 @@ mod cmd {
 
     // Copy multi selection to the cwd
     pub fn sel_copy(app: &mut App, _args: Vec<&str>) {
-        use tokio::fs;
-        fn copy_dir_recursive<'a>(
-            src: &'a std::path::Path,
-            dst: &'a std::path::Path,
-        ) -> std::pin::Pin<Box<dyn Future<Output = tokio::io::Result<()>> + Send + 'a>> {
-            Box::pin(async move {
-                fs::create_dir_all(dst).await?;
-                let mut dir = fs::read_dir(src).await?;
-                while let Some(entry) = dir.next_entry().await? {
-                    let file_type = entry.file_type().await?;
-                    let src_path = entry.path();
-                    let dst_path = dst.join(entry.file_name());
-                    if file_type.is_dir() {
-                        copy_dir_recursive(&src_path, &dst_path).await?;
-                    } else if file_type.is_file() {
-                        fs::copy(&src_path, &dst_path).await?;
-                    }
-                    // Symlinks and other types can be handled here if needed
-                }
-                Ok(())
-            })
-        }
         if app.multi_selection.is_empty() {
             app.set_output("Multi-select", "No items in multi selection to copy.");
             return;
         }
-        for path in app.multi_selection.iter() {
-            let path = path.clone();
-            let file_name = match path.file_name() {
-                Some(name) => name.to_owned(),
-                None => continue,
-            };
-            let dest_path = app.cwd.join(&file_name);
-            app.async_queue.add_task(aq::Kind::FsOperation, async move {
-                match fs::metadata(&path).await {
-                    Ok(meta) => {
-                        if meta.is_file() {
-                            match fs::copy(&path, &dest_path).await {
-                                Ok(_) => aq::ResData::as_str(
-                                    0,
-                                    format!(
-                                        "Copied file {} to {}",
-                                        path.to_string_lossy(),
-                                        dest_path.to_string_lossy()
-                                    ),
-                                ),
-                                Err(e) => aq::ResData::as_str(
-                                    1,
-                                    format!(
-                                        "Failed to copy file {}: {}",
-                                        path.to_string_lossy(),
-                                        e
-                                    ),
-                                ),
-                            }
-                        } else if meta.is_dir() {
-                            match copy_dir_recursive(&path, &dest_path).await {
-                                Ok(_) => aq::ResData::as_str(
+        app.set_output("Multi-select", "Copy tasks queued.");
+        conflict::transfer_sel(app, journal::OpKind::Copy);
+    }
+
+    // Move multi selection to the trash
+    pub fn sel_delete(app: &mut App, _args: Vec<&str>) {
+        if app.multi_selection.is_empty() {
+            app.set_output("Multi-select", "No items in multi selection to delete.");
+            return;
+        }
+        trash_paths(app, app.multi_selection.clone());
+        app.multi_selection.clear();
+        app.set_output("Multi-select", "Trash tasks queued.");
+    }
+
+    // Queue moving the paths to the trash as one undo batch
+    fn trash_paths(app: &mut App, paths: Vec<PathBuf>) {
+        let batch = app.journal.begin();
+        let opts = fs_ops::CopyOpts::from_cfg(&app.cfg);
+        for path in paths {
+            let label = format!("trash {}", path.to_string_lossy());
+            let retry_path = path.clone();
+            let id =
+                app.async_queue
+                    .add_task_progress(aq::Kind::FsOperation, label, move |progress| {
+                        let path = path.clone();
+                        async move {
+                            match trash::put(&path, opts, &progress).await {
+                                Ok(name) => aq::ResData::as_ops(
                                     0,
-                                    format!(
-                                        "Copied directory {} to {}",
-                                        path.to_string_lossy(),
-                                        dest_path.to_string_lossy()
-                                    ),
+                                    format!("Trashed {} as '{}'", path.to_string_lossy(), name),
+                                    vec![journal::Op::new(
+                                        batch,
+                                        journal::OpKind::Trash,
+                                        &path,
+                                        &PathBuf::from(&name),
+                                    )],
                                 ),
                                 Err(e) => aq::ResData::as_str(
                                     1,
-                                    format!(
-                                        "Failed to copy directory {}: {}",
-                                        path.to_string_lossy(),
-                                        e
-                                    ),
+                                    format!("Failed to trash {}: {}", path.to_string_lossy(), e),
                                 ),
                             }
-                        } else {
-                            aq::ResData::as_str(
-                                1,
-                                format!("Unsupported file type: {}", path.to_string_lossy()),
-                            )
                         }
-                    }
-                    Err(e) => aq::ResData::as_str(
-                        1,
-                        format!("Failed to stat {}: {}", path.to_string_lossy(), e),
-                    ),
-                }
-            });
+                    });
+            // A retry belongs in a new batch
+            app.async_queue.set_retry(
+                id,
+                aq::Retry::Rebuild(Box::new(move |app| trash_paths(app, vec![retry_path]))),
+            );
         }
-        app.multi_selection.clear();
-        app.set_output("Multi-select", "Copy tasks queued.");
     }
 
-    pub fn sel_delete(app: &mut App, _args: Vec<&str>) {
+    // Permanently delete multi selection, bypassing the trash
+    pub fn sel_delete_permanent(app: &mut App, _args: Vec<&str>) {
         use tokio::fs;
         if app.multi_selection.is_empty() {
             app.set_output("Multi-select", "No items in multi selection to delete.");
@@ -698,93 +1089,200 @@ mod cmd {
         }
         for path in app.multi_selection.iter() {
             let path = path.clone();
-            app.async_queue.add_task(aq::Kind::FsOperation, async move {
-                match fs::metadata(&path).await {
-                    Ok(meta) => {
-                        if meta.is_file() {
-                            match fs::remove_file(&path).await {
-                                Ok(_) => aq::ResData::as_str(
-                                    0,
-                                    format!("Deleted file {}", path.to_string_lossy()),
-                                ),
-                                Err(e) => aq::ResData::as_str(
-                                    1,
-                                    format!(
-                                        "Failed to delete file {}: {}",
-                                        path.to_string_lossy(),
-                                        e
-                                    ),
-                                ),
-                            }
-                        } else if meta.is_dir() {
-                            match fs::remove_dir_all(&path).await {
-                                Ok(_) => aq::ResData::as_str(
-                                    0,
-                                    format!("Deleted directory {}", path.to_string_lossy()),
-                                ),
-                                Err(e) => aq::ResData::as_str(
-                                    1,
-                                    format!(
-                                        "Failed to delete directory {}: {}",
-                                        path.to_string_lossy(),
-                                        e
-                                    ),
-                                ),
+            let label = format!("delete {}", path.to_string_lossy());
+            app.async_queue
+                .add_task_progress(aq::Kind::FsOperation, label, move |progress| {
+                    let path = path.clone();
+                    async move {
+                        match fs::metadata(&path).await {
+                            Ok(meta) => {
+                                if meta.is_file() {
+                                    match fs::remove_file(&path).await {
+                                        Ok(_) => aq::ResData::as_str(
+                                            0,
+                                            format!("Deleted file {}", path.to_string_lossy()),
+                                        ),
+                                        Err(e) => aq::ResData::as_str(
+                                            1,
+                                            format!(
+                                                "Failed to delete file {}: {}",
+                                                path.to_string_lossy(),
+                                                e
+                                            ),
+                                        ),
+                                    }
+                                } else if meta.is_dir() {
+                                    let res = match fs_ops::add_total(&path, &progress).await {
+                                        Ok(_) => fs_ops::remove_any(&path, &progress).await,
+                                        Err(e) => Err(e),
+                                    };
+                                    match res {
+                                        Ok(_) => aq::ResData::as_str(
+                                            0,
+                                            format!("Deleted directory {}", path.to_string_lossy()),
+                                        ),
+                                        Err(e) => aq::ResData::as_str(
+                                            1,
+                                            format!(
+                                                "Failed to delete directory {}: {}",
+                                                path.to_string_lossy(),
+                                                e
+                                            ),
+                                        ),
+                                    }
+                                } else {
+                                    aq::ResData::as_str(
+                                        1,
+                                        format!(
+                                            "{} is neither file nor directory",
+                                            path.to_string_lossy()
+                                        ),
+                                    )
+                                }
                             }
-                        } else {
-                            aq::ResData::as_str(
+                            Err(e) => aq::ResData::as_str(
                                 1,
-                                format!("{} is neither file nor directory", path.to_string_lossy()),
-                            )
+                                format!(
+                                    "Failed to get metadata for {}: {}",
+                                    path.to_string_lossy(),
+                                    e
+                                ),
+                            ),
                         }
                     }
-                    Err(e) => aq::ResData::as_str(
-                        1,
-                        format!(
-                            "Failed to get metadata for {}: {}",
-                            path.to_string_lossy(),
-                            e
-                        ),
-                    ),
-                }
-            });
+                });
         }
         app.multi_selection.clear();
         app.set_output("Multi-select", "Delete tasks queued.");
     }
 
+    // Move multi selection to the cwd
     pub fn sel_move(app: &mut App, _args: Vec<&str>) {
-        use tokio::fs;
         if app.multi_selection.is_empty() {
             app.set_output("Multi-select", "No items in multi selection to move.");
             return;
         }
-        for path in app.multi_selection.iter() {
-            let path = path.clone();
-            let file_name = match path.file_name() {
-                Some(name) => name.to_owned(),
-                None => continue,
-            };
-            let dest_path = app.cwd.join(&file_name);
-            app.async_queue.add_task(aq::Kind::FsOperation, async move {
-                match fs::rename(&path, &dest_path).await {
-                    Ok(_) => aq::ResData::as_str(
-                        0,
-                        format!(
-                            "Moved {} to {}",
-                            path.to_string_lossy(),
-                            dest_path.to_string_lossy()
+        app.set_output("Multi-select", "Move tasks queued.");
+        conflict::transfer_sel(app, journal::OpKind::Move);
+    }
+
+    pub fn trash_list(app: &mut App, _args: Vec<&str>) {
+        let items = trash::list();
+        if items.is_empty() {
+            app.set_output("Trash", "The trash is empty.");
+            return;
+        }
+        let mut text = format!("Path: {}\n\n", trash::get_path().to_str().unwrap());
+        for item in items.iter() {
+            text += &format!(
+                "{:<24} : {} ({})\n",
+                item.name,
+                item.orig.to_str().unwrap_or("?"),
+                item.deleted
+            );
+        }
+        app.set_output("Trash", &text);
+    }
+
+    pub fn trash_restore(app: &mut App, args: Vec<&str>) {
+        if args.is_empty() {
+            app.set_output(
+                "Trash",
+                "Error: No trash item name provided (see trash-list).",
+            );
+            return;
+        }
+        // Trash names may contain spaces
+        let name = args.join(" ");
+        let label = format!("restore {}", name);
+        let opts = fs_ops::CopyOpts::from_cfg(&app.cfg);
+        app.async_queue
+            .add_task_progress(aq::Kind::FsOperation, label, move |progress| {
+                let name = name.clone();
+                async move {
+                    match trash::restore(&name, opts, &progress).await {
+                        Ok(orig) => aq::ResData::as_str(
+                            0,
+                            format!("Restored '{}' to {}", name, orig.to_string_lossy()),
                         ),
-                    ),
-                    Err(e) => aq::ResData::as_str(
-                        1,
-                        format!("Failed to move {}: {}", path.to_string_lossy(), e),
-                    ),
+                        Err(e) => {
+                            aq::ResData::as_str(1, format!("Failed to restore '{}': {}", name, e))
+                        }
+                    }
                 }
             });
+        app.set_output("Trash", "Restore task queued.");
+    }
+
+    pub fn trash_empty(app: &mut App, _args: Vec<&str>) {
+        app.async_queue.add_task(aq::Kind::FsOperation, async move {
+            match trash::empty().await {
+                Ok(count) => aq::ResData::as_str(0, format!("Emptied trash ({} items)", count)),
+                Err(e) => aq::ResData::as_str(1, format!("Failed to empty trash: {}", e)),
+            }
+        });
+        app.set_output("Trash", "Empty trash task queued.");
+    }
+
+    pub fn undo(app: &mut App, _args: Vec<&str>) {
+        if app.async_queue.has_pending(&aq::Kind::FsOperation) {
+            app.set_output("Undo", "Wait for pending file operations to finish first.");
+            return;
         }
-        app.multi_selection.clear();
-        app.set_output("Multi-select", "Move tasks queued.");
+        match app.journal.undo() {
+            Ok(batch) => {
+                let label = format!("undo {} operations", batch.len());
+                let opts = fs_ops::CopyOpts::from_cfg(&app.cfg);
+                let id = app.async_queue.add_task_progress(
+                    aq::Kind::FsOperation,
+                    label,
+                    move |progress| {
+                        let batch = batch.clone();
+                        async move {
+                            let (rc, out, undone) =
+                                journal::undo_batch(batch, opts, progress).await;
+                            aq::ResData::as_replayed(rc, out, vec![], undone)
+                        }
+                    },
+                );
+                // What is left is undone by running undo again
+                app.async_queue.set_retry(id, aq::Retry::Never);
+                app.set_output("Undo", "Undo task queued.");
+            }
+            Err(e) => app.set_output("Undo", &e),
+        }
+    }
+
+    pub fn redo(app: &mut App, _args: Vec<&str>) {
+        if app.async_queue.has_pending(&aq::Kind::FsOperation) {
+            app.set_output("Redo", "Wait for pending file operations to finish first.");
+            return;
+        }
+        match app.journal.redo() {
+            Ok((batch, new_batch)) => {
+                let label = format!("redo {} operations", batch.len());
+                let opts = fs_ops::CopyOpts::from_cfg(&app.cfg);
+                let id = app.async_queue.add_task_progress(
+                    aq::Kind::FsOperation,
+                    label,
+                    move |progress| {
+                        let batch = batch.clone();
+                        async move {
+                            let (rc, out, ops, redone) =
+                                journal::redo_batch(batch, new_batch, opts, progress).await;
+                            aq::ResData::as_replayed(rc, out, ops, redone)
+                        }
+                    },
+                );
+                app.async_queue.set_retry(id, aq::Retry::Never);
+                app.set_output("Redo", "Redo task queued.");
+            }
+            Err(e) => app.set_output("Redo", &e),
+        }
+    }
+
+    pub fn jobs(app: &mut App, _args: Vec<&str>) {
+        app.show_jobs_window = !app.show_jobs_window;
     }
 
     pub fn sel_clip_path(app: &mut App, _args: Vec<&str>) {
@@ -817,6 +1315,9 @@ mod cmd {
 
     pub fn cmd_finder_toggle(app: &mut App, _args: Vec<&str>) {
         app.mode_cmd_finder = !app.mode_cmd_finder;
+        app.mode_jump = false;
+        app.mode_history = false;
+        app.mode_bookmarks = false;
         if app.mode_cmd_finder {
             app.search_buf = String::new();
         }
@@ -955,26 +1456,111 @@ mod cmd {
         app.set_output("Keybinds", &out);
     }
 
-    pub fn hidden_toggle(app: &mut App, _args: Vec<&str>) {
-        app.cfg.show_hidden = !app.cfg.show_hidden;
-        app.update_listing();
+    // Cycle the search match mode, or set it by name
+    pub fn match_mode(app: &mut App, args: Vec<&str>) {
+        app.cfg.match_mode = match args.first() {
+            Some(name) => match matching::Mode::from_str(name) {
+                Some(mode) => mode,
+                None => {
+                    app.set_output(
+                        "Match mode",
+                        "Unknown mode, expected one of: fuzzy, substring, prefix, regex, glob",
+                    );
+                    return;
+                }
+            },
+            None => app.cfg.match_mode.next(),
+        };
         app.update_results();
         app.focus_index = 0;
     }
 
-    // Edit the focused file
-    pub fn edit(app: &mut App, _args: Vec<&str>) {
-        let focused_path = app.find_focused_path();
-        let editor = env::var("EDITOR").unwrap_or("vi".to_string());
-        log!(
-            "Opening editor: {} {}",
-            editor,
-            focused_path.to_str().unwrap()
-        );
-        match Command::new(editor)
-            .arg(focused_path.to_str().unwrap())
-            .status()
-        {
+    // Cycle the sort key, or set it by name with an optional order
+    pub fn sort(app: &mut App, args: Vec<&str>) {
+        let key = match args.first() {
+            Some(name) => match sort::Key::from_str(name) {
+                Some(key) => key,
+                None => {
+                    app.set_output(
+                        "Sort",
+                        "Unknown sort, expected one of: name, size, mtime, ext, type",
+                    );
+                    return;
+                }
+            },
+            None => app.cfg.sort.key.next(),
+        };
+        let desc = match args.get(1).map(|order| order.to_lowercase()) {
+            Some(order) if order == "asc" => false,
+            Some(order) if order == "desc" => true,
+            Some(_) => {
+                app.set_output("Sort", "Unknown order, expected asc or desc");
+                return;
+            }
+            None => false,
+        };
+        app.cfg.sort.key = key;
+        app.cfg.sort.desc = desc;
+        app.resort();
+    }
+
+    // Toggle grouping directories before files
+    pub fn sort_dirs_first(app: &mut App, _args: Vec<&str>) {
+        app.cfg.sort.dirs_first = !app.cfg.sort.dirs_first;
+        app.resort();
+    }
+
+    // Toggle searching file contents instead of names
+    pub fn content_search(app: &mut App, _args: Vec<&str>) {
+        app.mode_grep = !app.mode_grep;
+        app.update_results();
+        app.focus_index = 0;
+    }
+
+    // Switch the focused file between the hex dump and the text preview
+    pub fn hex_toggle(app: &mut App, _args: Vec<&str>) {
+        if !app.focused.is_file_like() || app.focused.is_dir() {
+            app.set_output("Error", "Focus a file to toggle its hex preview.");
+            return;
+        }
+        let path = app.cwd.join(&app.focused.name);
+        app.hex_flip = if app.hex_flip.as_ref() == Some(&path) {
+            None
+        } else {
+            Some(path)
+        };
+        app.update_preview();
+    }
+
+    pub fn hidden_toggle(app: &mut App, _args: Vec<&str>) {
+        app.cfg.show_hidden = !app.cfg.show_hidden;
+        app.update_listing();
+        app.update_results();
+        app.focus_index = 0;
+    }
+
+    // Toggle the size, mtime, permission and owner columns in the listing
+    pub fn long_toggle(app: &mut App, _args: Vec<&str>) {
+        app.cfg.long_listing = !app.cfg.long_listing;
+    }
+
+    // Edit the focused file
+    pub fn edit(app: &mut App, _args: Vec<&str>) {
+        let focused_path = app.find_focused_path();
+        let editor = env::var("EDITOR").unwrap_or("vi".to_string());
+        log!(
+            "Opening editor: {} {}",
+            editor,
+            focused_path.to_str().unwrap()
+        );
+        let mut command = Command::new(editor);
+        // Most editors accept "+line" to open at a line
+        let location = grep::parse(&app.focused.name)
+            .filter(|_| app.multi_selection.is_empty() && app.focused.is_match());
+        if let Some((_, line)) = location {
+            command.arg(format!("+{}", line));
+        }
+        match command.arg(focused_path.to_str().unwrap()).status() {
             Ok(_) => {}
             Err(e) => {
                 app.set_output("Editor", &format!("Failed to open editor: {}", e));
@@ -1011,6 +1597,13 @@ mod cmd {
         new_dir_path.push(dir_name);
         match fs::create_dir(&new_dir_path) {
             Ok(_) => {
+                let batch = app.journal.begin();
+                app.journal.record(journal::Op::new(
+                    batch,
+                    journal::OpKind::MkDir,
+                    &new_dir_path,
+                    &PathBuf::new(),
+                ));
                 app.set_output(
                     "Mkdir",
                     &format!("Directory '{}' created.", new_dir_path.to_str().unwrap()),
@@ -1031,344 +1624,961 @@ mod cmd {
         }
     }
 
-    pub fn goto(app: &mut App, args: Vec<&str>) {
+    pub fn rename(app: &mut App, args: Vec<&str>) {
         if args.is_empty() {
-            app.set_output("Goto", "Error: No path provided.");
+            app.set_output("Rename", "Error: No new name provided.");
             return;
         }
-        let path = PathBuf::from(args[0]);
-        app.append_cwd(&path);
-        app.update_listing();
-        app.update_results();
-        app.focus_index = 0;
-    }
-
-    pub fn input_clear(app: &mut App, _args: Vec<&str>) {
-        app.search_buf.clear();
-        app.command_input.clear();
-        app.update_results();
-        app.focus_index = 0;
-    }
-
-    pub fn shell_quick(app: &mut App, args: Vec<&str>) {
-        // Join args into a single command string
-        let mut shell_cmd = args[0..].join(" ");
-
-        // Replace variables
-        shell_cmd = app.replace_shell_vars(shell_cmd);
-
-        // Add the cwd prefix
-        shell_cmd = format!("cd '{}' && {}", app.cwd.to_str().unwrap(), shell_cmd);
-
-        // Run the command
-        log!("Running shell command: {}", shell_cmd);
-        let out: String;
-        match Command::new(app.user_shell.as_str())
-            .arg("-c")
-            .arg(shell_cmd)
-            .output()
-        {
-            Ok(output) => {
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                out = format!("{}{}", stdout, stderr);
-            }
-            Err(e) => {
-                out = format!("Failed to run command: {}", e);
-            }
+        if !app.focused.is_file_like() {
+            app.set_output("Rename", "Error: Nothing to rename.");
+            return;
         }
-        if !out.is_empty() {
-            app.set_output("Shell", &out);
+        let new_name = args.join(" ");
+        if new_name.contains('/') {
+            app.set_output("Rename", "Error: Names cannot contain '/'.");
+            return;
         }
-        app.update_listing();
-        app.update_results();
-    }
-
-    pub fn shell_full(app: &mut App, _args: Vec<&str>) {
-        let shell = env::var("SHELL").unwrap_or("/bin/sh".to_string());
-        // Set the real program cwd
-        env::set_current_dir(&app.cwd).unwrap_or(());
-        log!("Opening shell: {}", shell);
-        util::cls();
-        match Command::new(shell).status() {
-            Ok(_) => {
-                app.set_output("Shell", "Shell closed.");
-            }
-            Err(e) => {
-                app.set_output("Shell", &format!("Failed to open shell: {}", e));
-            }
+        let from = app.cwd.join(&app.focused.name);
+        let to = from.with_file_name(&new_name);
+        match rename::validate(vec![(from, to)]) {
+            Ok(plan) if plan.is_empty() => app.set_output("Rename", "Nothing to rename."),
+            Ok(plan) => rename::commit(app, plan),
+            Err(e) => app.set_output("Rename", &format!("Error: {}", e)),
         }
-        util::cls();
-        app.term_clear = true;
     }
 
-    pub fn config_init(app: &mut App, _args: Vec<&str>) {
-        // Write the default files to the config directory
-        let config_path = cfg::Config::get_path();
-        let colors_path = cs::Colors::get_path();
-        let kb_path = kb::get_path();
-        let shell_cmds_path = shell_cmds::get_path();
-        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
-        fs::create_dir_all(colors_path.parent().unwrap()).unwrap();
-        fs::create_dir_all(kb_path.parent().unwrap()).unwrap();
-        fs::create_dir_all(shell_cmds_path.parent().unwrap()).unwrap();
-        let mut output_text = String::new();
-        match fs::write(&config_path, cfg::DEFAULT) {
-            Ok(_) => {
-                output_text += &format!("Created config file: {}\n", config_path.to_str().unwrap());
-            }
-            Err(e) => {
-                output_text += &format!(
-                    "Failed to create config file: {} ({})\n",
-                    config_path.to_str().unwrap(),
-                    e
+    // Edit the names of the selection (or the listing) in $EDITOR
+    pub fn bulk_rename(app: &mut App, _args: Vec<&str>) {
+        let paths = if app.multi_selection.is_empty() {
+            app.listing
+                .iter()
+                .filter(|node| node.is_file_like())
+                .map(|node| app.cwd.join(&node.name))
+                .collect::<Vec<PathBuf>>()
+        } else {
+            app.multi_selection.clone()
+        };
+        if paths.is_empty() {
+            app.set_output("Bulk rename", "Nothing to rename.");
+            return;
+        }
+        let mut content = String::new();
+        for path in paths.iter() {
+            let path = path.to_string_lossy();
+            if path.contains('\n') {
+                app.set_output(
+                    "Bulk rename",
+                    "Error: Names with newlines cannot be edited.",
                 );
+                return;
             }
+            content += &format!("{}\n", path);
         }
-        match fs::write(&colors_path, cs::DEFAULT) {
-            Ok(_) => {
-                output_text += &format!("Created colors file: {}\n", colors_path.to_str().unwrap());
-            }
+        // Other users must not be able to read or swap the list
+        let write_tmp = || -> std::io::Result<PathBuf> {
+            use std::io::Write;
+            use std::os::unix::fs::OpenOptionsExt;
+            let dir = util::private_dir(&env::temp_dir(), "bulk-rename")?;
+            let tmp_path = dir.join("names.txt");
+            fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&tmp_path)
+                .and_then(|mut file| file.write_all(content.as_bytes()))
+                .inspect_err(|_| {
+                    let _ = fs::remove_dir_all(&dir);
+                })?;
+            Ok(tmp_path)
+        };
+        let tmp_path = match write_tmp() {
+            Ok(tmp_path) => tmp_path,
             Err(e) => {
-                output_text += &format!(
-                    "Failed to create colors file: {} ({})\n",
-                    colors_path.to_str().unwrap(),
-                    e
-                );
+                app.set_output("Bulk rename", &format!("Failed to write temp file: {}", e));
+                return;
             }
+        };
+        let editor = env::var("EDITOR").unwrap_or("vi".to_string());
+        log!("Opening editor: {} {}", editor, tmp_path.to_string_lossy());
+        let status = Command::new(editor).arg(&tmp_path).status();
+        let edited = fs::read_to_string(&tmp_path);
+        // Editors may leave swap or backup files next to it
+        if let Some(dir) = tmp_path.parent() {
+            let _ = fs::remove_dir_all(dir);
         }
-        match fs::write(&kb_path, kb::DEFAULT) {
+        match status {
+            Ok(status) if status.success() => {}
             Ok(_) => {
-                output_text += &format!("Created keybinds file: {}\n", kb_path.to_str().unwrap());
+                app.set_output(
+                    "Bulk rename",
+                    "Editor exited with an error, nothing renamed.",
+                );
+                return;
             }
             Err(e) => {
-                output_text += &format!(
-                    "Failed to create keybinds file: {} ({})\n",
-                    kb_path.to_str().unwrap(),
-                    e
-                );
+                app.set_output("Bulk rename", &format!("Failed to open editor: {}", e));
+                return;
             }
         }
-        match fs::write(&shell_cmds_path, shell_cmds::DEFAULT) {
-            Ok(_) => {
-                output_text += &format!(
-                    "Created shell commands file: {}\n",
-                    shell_cmds_path.to_str().unwrap()
-                );
-            }
+        let edited = match edited {
+            Ok(edited) => edited,
             Err(e) => {
-                output_text += &format!(
-                    "Failed to create shell commands file: {} ({})\n",
-                    shell_cmds_path.to_str().unwrap(),
-                    e
-                );
+                app.set_output("Bulk rename", &format!("Failed to read temp file: {}", e));
+                return;
             }
+        };
+        let lines = edited.trim_end_matches('\n').lines().collect::<Vec<&str>>();
+        if lines.len() != paths.len() {
+            app.set_output(
+                "Bulk rename",
+                &format!(
+                    "Error: Expected {} lines but found {}. Lines must not be added or removed.",
+                    paths.len(),
+                    lines.len()
+                ),
+            );
+            return;
+        }
+        // Relative names are taken as relative to the current directory
+        let pairs = paths
+            .into_iter()
+            .zip(lines.iter().map(|line| app.cwd.join(line)))
+            .collect::<Vec<(PathBuf, PathBuf)>>();
+        match rename::validate(pairs) {
+            Ok(plan) => rename::confirm(app, "Bulk rename", plan),
+            Err(e) => app.set_output("Bulk rename", &format!("Nothing renamed.\n{}", e)),
         }
-        app.set_output("Config Init", &output_text);
     }
 
-    pub fn config_clear(app: &mut App, _args: Vec<&str>) {
-        let config_path = cfg::Config::get_path();
-        let colors_path = cs::Colors::get_path();
-        let kb_path = kb::get_path();
-        let shell_cmds_path = shell_cmds::get_path();
-        let mut output_text = String::new();
-        match fs::remove_file(&config_path) {
-            Ok(_) => {
-                output_text += &format!("Removed config file: {}\n", config_path.to_str().unwrap());
-            }
-            Err(e) => {
-                output_text += &format!(
-                    "Failed to remove config file: {} ({})\n",
-                    config_path.to_str().unwrap(),
-                    e
-                );
-            }
+    // Rename with a regex, see rename::pattern_pairs
+    pub fn rename_pattern(app: &mut App, args: Vec<&str>) {
+        if args.len() < 2 {
+            app.set_output("Rename pattern", "Error: Usage: rename-pattern <from> <to>");
+            return;
         }
-        match fs::remove_file(&colors_path) {
-            Ok(_) => {
-                output_text += &format!("Removed colors file: {}\n", colors_path.to_str().unwrap());
-            }
+        let paths = app.rename_pattern_targets();
+        let pairs = match rename::pattern_pairs(&paths, args[0], &args[1..].join(" ")) {
+            Ok(pairs) => pairs,
             Err(e) => {
-                output_text += &format!(
-                    "Failed to remove colors file: {} ({})\n",
-                    colors_path.to_str().unwrap(),
-                    e
-                );
+                app.set_output("Rename pattern", &format!("Error: {}", e));
+                return;
             }
+        };
+        match rename::validate(pairs) {
+            Ok(plan) if plan.is_empty() => app.set_output("Rename pattern", "Nothing to rename."),
+            Ok(plan) => rename::commit(app, plan),
+            Err(e) => app.set_output("Rename pattern", &format!("Nothing renamed.\n{}", e)),
         }
-        match fs::remove_file(&kb_path) {
-            Ok(_) => {
-                output_text += &format!("Removed keybinds file: {}\n", kb_path.to_str().unwrap());
+    }
+
+    // Only paths below the cwd, so a new file can be focused
+    fn relative_name(name: &str) -> Result<PathBuf, &'static str> {
+        let mut rel = PathBuf::new();
+        for component in Path::new(name).components() {
+            match component {
+                std::path::Component::Normal(part) => rel.push(part),
+                std::path::Component::CurDir => {}
+                _ => {
+                    return Err(
+                        "Error: The name must be relative to the current directory and not contain '..'.",
+                    );
+                }
             }
+        }
+        if rel.as_os_str().is_empty() {
+            return Err("Error: No file name provided.");
+        }
+        Ok(rel)
+    }
+
+    pub fn touch(app: &mut App, args: Vec<&str>) {
+        if args.is_empty() {
+            app.set_output("Touch", "Error: No file name provided.");
+            return;
+        }
+        let rel = match relative_name(&args.join(" ")) {
+            Ok(rel) => rel,
             Err(e) => {
-                output_text += &format!(
-                    "Failed to remove keybinds file: {} ({})\n",
-                    kb_path.to_str().unwrap(),
-                    e
-                );
+                app.set_output("Touch", e);
+                return;
             }
+        };
+        let path = app.cwd.join(&rel);
+        let parent = path.parent().unwrap();
+        if let Err(e) = fs::create_dir_all(parent) {
+            app.set_output(
+                "Touch",
+                &format!(
+                    "Error: Failed to create '{}': {}",
+                    parent.to_str().unwrap(),
+                    e
+                ),
+            );
+            return;
         }
-        match fs::remove_file(&shell_cmds_path) {
-            Ok(_) => {
-                output_text += &format!(
-                    "Removed shell commands file: {}\n",
-                    shell_cmds_path.to_str().unwrap()
-                );
-            }
+        match fs::File::create_new(&path) {
+            Ok(_) => app.set_output(
+                "Touch",
+                &format!("File '{}' created.", path.to_str().unwrap()),
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => app.set_output(
+                "Touch",
+                &format!("'{}' already exists.", path.to_str().unwrap()),
+            ),
             Err(e) => {
-                output_text += &format!(
-                    "Failed to remove shell commands file: {} ({})\n",
-                    shell_cmds_path.to_str().unwrap(),
-                    e
+                app.set_output(
+                    "Touch",
+                    &format!(
+                        "Error: Failed to create file '{}': {}",
+                        path.to_str().unwrap(),
+                        e
+                    ),
                 );
+                return;
             }
         }
-        app.set_output("Config Clear", &output_text);
+        app.focus_created(&rel);
     }
 
-    pub fn config_goto(app: &mut App, _args: Vec<&str>) {
-        let config_path = cfg::Config::get_path();
-        app.append_cwd(&config_path.parent().unwrap().to_path_buf());
+    pub fn new_from_template(app: &mut App, args: Vec<&str>) {
+        if args.is_empty() {
+            let list = templates::list();
+            app.set_output(
+                "Template",
+                &format!(
+                    "Error: No template provided.\nTemplates in {}:\n{}",
+                    templates::get_path().to_str().unwrap(),
+                    list.join("\n")
+                ),
+            );
+            return;
+        }
+        // The name defaults to the template name
+        let name = if args.len() > 1 {
+            args[1..].join(" ")
+        } else {
+            args[0].to_string()
+        };
+        let rel = match relative_name(&name) {
+            Ok(rel) => rel,
+            Err(e) => {
+                app.set_output("Template", e);
+                return;
+            }
+        };
+        let path = app.cwd.join(&rel);
+        match templates::instantiate(args[0], &path) {
+            Ok(_) => {
+                app.set_output(
+                    "Template",
+                    &format!(
+                        "Created '{}' from template '{}'.",
+                        path.to_str().unwrap(),
+                        args[0]
+                    ),
+                );
+                app.focus_created(&rel);
+            }
+            Err(e) => app.set_output("Template", &format!("Error: {}", e)),
+        }
+    }
+
+    // The multi selection, or the focused item if nothing is selected
+    fn sel_or_focused(app: &App) -> Vec<PathBuf> {
+        if !app.multi_selection.is_empty() {
+            return app.multi_selection.clone();
+        }
+        if app.focused.is_file_like() {
+            return vec![app.cwd.join(&app.focused.name)];
+        }
+        Vec::new()
+    }
+
+    // Extract archives into new folders in the cwd
+    // An optional argument names the folder when extracting a single archive
+    pub fn extract(app: &mut App, args: Vec<&str>) {
+        let archives = sel_or_focused(app);
+        if archives.is_empty() {
+            app.set_output("Extract", "No archives selected.");
+            return;
+        }
+        let mut output = String::new();
+        for path in archives.iter() {
+            if archive::Format::from_path(path).is_none() {
+                output += &format!(
+                    "Skipped {}: not a supported archive ({})\n",
+                    path.to_string_lossy(),
+                    archive::SUPPORTED
+                );
+                continue;
+            }
+            let dest = if !args.is_empty() && archives.len() == 1 {
+                app.cwd.join(args.join(" "))
+            } else {
+                let dest = app.cwd.join(archive::stem(path));
+                if dest.symlink_metadata().is_ok() {
+                    conflict::unique_name(&dest)
+                } else {
+                    dest
+                }
+            };
+            let label = format!(
+                "extract {} -> {}",
+                path.to_string_lossy(),
+                dest.to_string_lossy()
+            );
+            output += &format!("Extract task queued for {}\n", path.to_string_lossy());
+            let path = path.clone();
+            app.async_queue
+                .add_task_progress(aq::Kind::FsOperation, label, move |progress| {
+                    let (path, dest) = (path.clone(), dest.clone());
+                    async move {
+                        let (src, dst) = (path.clone(), dest.clone());
+                        let res = tokio::task::spawn_blocking(move || {
+                            archive::extract(&src, &dst, &progress)
+                        })
+                        .await;
+                        match res {
+                            Ok(Ok(count)) => aq::ResData::as_str(
+                                0,
+                                format!(
+                                    "Extracted {} entries from {} into {}",
+                                    count,
+                                    path.to_string_lossy(),
+                                    dest.to_string_lossy()
+                                ),
+                            ),
+                            Ok(Err(e)) => aq::ResData::as_str(
+                                1,
+                                format!("Failed to extract {}: {}", path.to_string_lossy(), e),
+                            ),
+                            Err(e) => aq::ResData::as_str(
+                                1,
+                                format!("Failed to extract {}: {}", path.to_string_lossy(), e),
+                            ),
+                        }
+                    }
+                });
+        }
+        app.multi_selection.clear();
+        app.set_output("Extract", &output);
+    }
+
+    // Create an archive in the cwd from the selection
+    pub fn compress(app: &mut App, args: Vec<&str>) {
+        if args.is_empty() {
+            app.set_output("Compress", "Error: No archive name provided.");
+            return;
+        }
+        let paths = sel_or_focused(app);
+        if paths.is_empty() {
+            app.set_output("Compress", "No items in multi selection to compress.");
+            return;
+        }
+        let dest = app.cwd.join(args.join(" "));
+        if archive::Format::from_path(&dest).is_none() {
+            app.set_output(
+                "Compress",
+                &format!(
+                    "Error: Unknown archive extension, expected one of: {}",
+                    archive::SUPPORTED
+                ),
+            );
+            return;
+        }
+        if dest.symlink_metadata().is_ok() {
+            app.set_output(
+                "Compress",
+                &format!("Error: {} already exists.", dest.to_string_lossy()),
+            );
+            return;
+        }
+        let label = format!(
+            "compress {} items -> {}",
+            paths.len(),
+            dest.to_string_lossy()
+        );
+        app.async_queue
+            .add_task_progress(aq::Kind::FsOperation, label, move |progress| {
+                let (paths, dest) = (paths.clone(), dest.clone());
+                async move {
+                    for path in paths.iter() {
+                        let _ = fs_ops::add_total(path, &progress).await;
+                    }
+                    let dst = dest.clone();
+                    let res = tokio::task::spawn_blocking(move || {
+                        archive::compress(&paths, &dst, &progress)
+                    })
+                    .await;
+                    match res {
+                        Ok(Ok(count)) => aq::ResData::as_str(
+                            0,
+                            format!(
+                                "Compressed {} entries into {}",
+                                count,
+                                dest.to_string_lossy()
+                            ),
+                        ),
+                        Ok(Err(e)) => aq::ResData::as_str(
+                            1,
+                            format!("Failed to create {}: {}", dest.to_string_lossy(), e),
+                        ),
+                        Err(e) => aq::ResData::as_str(
+                            1,
+                            format!("Failed to create {}: {}", dest.to_string_lossy(), e),
+                        ),
+                    }
+                }
+            });
+        app.multi_selection.clear();
+        app.set_output("Compress", "Compress task queued.");
+    }
+
+    pub fn goto(app: &mut App, args: Vec<&str>) {
+        if args.is_empty() {
+            app.set_output("Goto", "Error: No path provided.");
+            return;
+        }
+        let path = PathBuf::from(args[0]);
+        app.append_cwd(&path);
         app.update_listing();
         app.update_results();
+        app.focus_index = 0;
     }
 
-    pub fn dbg_clear_preview(app: &mut App, _args: Vec<&str>) {
-        app.term_clear = true;
+    // Toggle fuzzy searching the most visited directories
+    pub fn jump(app: &mut App, _args: Vec<&str>) {
+        app.mode_jump = !app.mode_jump;
+        app.mode_history = false;
+        app.mode_bookmarks = false;
+        app.mode_cmd_finder = false;
+        app.search_buf = String::new();
+        app.update_listing();
+        app.update_results();
+        app.focus_index = 0;
     }
-}
 
-// Command metadata
-mod cmd_data {
-    use std::collections::HashMap;
+    // Add the directories known to zoxide and/or autojump to the jump list
+    pub fn jump_import(app: &mut App, args: Vec<&str>) {
+        let sources = match args.first() {
+            Some(&"zoxide") => vec!["zoxide"],
+            Some(&"autojump") => vec!["autojump"],
+            Some(other) => {
+                app.set_output(
+                    "Jump import",
+                    &format!("Unknown source '{}', expected zoxide or autojump.", other),
+                );
+                return;
+            }
+            None => vec!["zoxide", "autojump"],
+        };
+        let mut text = String::new();
+        for source in sources {
+            let found = match source {
+                "zoxide" => frecency::read_zoxide(),
+                _ => frecency::read_autojump(),
+            };
+            match found {
+                Ok(found) => {
+                    let count = found.len();
+                    let added = app.frecency.import(found);
+                    text += &format!("{}: {} directories, {} new\n", source, count, added);
+                }
+                Err(e) => text += &format!("{}: {}\n", source, e),
+            }
+        }
+        app.set_output("Jump import", &text);
+        if app.mode_jump {
+            app.update_listing();
+            app.update_results();
+        }
+    }
 
-    use crate::{App, cmd, cmd_data};
+    // Remove directories which no longer exist from the jump list
+    pub fn jump_prune(app: &mut App, _args: Vec<&str>) {
+        let removed = app.frecency.prune();
+        app.set_output(
+            "Jump prune",
+            &format!("Removed {} missing directories.", removed),
+        );
+        if app.mode_jump {
+            app.update_listing();
+            app.update_results();
+        }
+    }
 
-    #[derive(Hash, Eq, PartialEq, Debug, Clone, PartialOrd, Ord)]
-    pub enum CmdName {
-        Exit,
-        Home,
-        CurUp,
-        CurDown,
-        DirUp,
-        DirBack,
-        DirReload,
-        Explode,
-        Enter,
-        CmdWinToggle,
-        CmdFinderToggle,
-        CmdList,
-        CmdListDump,
-        OutputWinToggle,
-        OutputWinShow,
-        OutputWinHide,
-        Sel,
-        SelClear,
-        SelShow,
-        SelSave,
-        Copy,
-        Delete,
-        Move,
-        ClipPath,
-        MenuBack,
-        Log,
-        LogClear,
-        SecUp,
-        SecDown,
-        KeybindsShow,
-        DbgClear,
-        Edit,
-        OsOpen,
-        MkDir,
-        GoTo,
-        HiddenToggle,
-        InputClear,
-        ShellQuick,
-        ShellFull,
-        ConfigInit,
-        ConfigClear,
-        ConfigGoto,
+    // Toggle picking from the bookmarks
+    pub fn bookmarks(app: &mut App, _args: Vec<&str>) {
+        app.mode_bookmarks = !app.mode_bookmarks;
+        app.mode_jump = false;
+        app.mode_history = false;
+        app.mode_cmd_finder = false;
+        app.search_buf = String::new();
+        app.update_listing();
+        app.update_results();
+        app.focus_index = 0;
     }
 
-    #[derive(Debug, Clone)]
-    pub struct CmdData {
-        pub fname: &'static str,
-        pub description: &'static str,
-        pub cmd: &'static str,
-        pub vis_hidden: bool, // Hidden from visual cmd selection
-        pub params: Vec<&'static str>,
-        pub on_sel: bool, // Operates on multi selection
-        pub op: fn(&mut App, Vec<&str>) -> (),
+    // Bookmark the current directory, replacing a bookmark with the same name
+    pub fn bookmark_add(app: &mut App, args: Vec<&str>) {
+        let Some(name) = args.first() else {
+            app.set_output("Bookmark", "Error: No name provided.");
+            return;
+        };
+        if archive::split(&app.cwd).is_some() {
+            app.set_output(
+                "Bookmark",
+                "Error: Can't bookmark a directory inside an archive.",
+            );
+            return;
+        }
+        let target = fs::canonicalize(&app.cwd).unwrap_or(app.cwd.clone());
+        app.bookmarks.retain(|(bm_name, _)| bm_name != name);
+        app.bookmarks.push((name.to_string(), target.clone()));
+        app.bookmarks.sort();
+        match bookmarks::save(&app.bookmarks) {
+            Ok(_) => app.set_output(
+                "Bookmark",
+                &format!("Added {} -> {}", name, target.to_string_lossy()),
+            ),
+            Err(e) => app.set_output("Bookmark", &format!("Error: {}", e)),
+        }
     }
-    pub type CmdList = HashMap<CmdName, CmdData>;
-    pub fn cmd_name_from_str(
-        cmd_list: &HashMap<CmdName, cmd_data::CmdData>,
-        cmd: &str,
-    ) -> Option<cmd_data::CmdName> {
-        for (name, data) in cmd_list.iter() {
-            if data.cmd == cmd {
-                return Some(name.clone());
+
+    // Remove the named bookmark, or the focused one in the bookmark picker
+    pub fn bookmark_del(app: &mut App, args: Vec<&str>) {
+        let name = match args.first() {
+            Some(name) => name.to_string(),
+            None if app.mode_bookmarks => app.focused.name.clone(),
+            None => {
+                app.set_output(
+                    "Bookmark",
+                    "Error: No name provided. Give a name or pick one in the bookmark list.",
+                );
+                return;
             }
+        };
+        if bookmarks::find(&app.bookmarks, &name).is_none() {
+            app.set_output("Bookmark", &format!("Error: No bookmark named {}.", name));
+            return;
+        }
+        app.bookmarks.retain(|(bm_name, _)| *bm_name != name);
+        match bookmarks::save(&app.bookmarks) {
+            Ok(_) => app.set_output("Bookmark", &format!("Removed {}", name)),
+            Err(e) => app.set_output("Bookmark", &format!("Error: {}", e)),
+        }
+        if app.mode_bookmarks {
+            app.update_listing();
+            app.update_results();
         }
-        None
     }
-    pub fn get_cmd_data(
-        cmd_list: &cmd_data::CmdList,
-        name: &cmd_data::CmdName,
-    ) -> cmd_data::CmdData {
-        match cmd_list.get(name) {
-            Some(data) => data.clone(),
-            None => panic!("Command not found: {:?}", name),
+
+    pub fn goto_bookmark(app: &mut App, args: Vec<&str>) {
+        let Some(name) = args.first() else {
+            app.set_output("Bookmark", "Error: No name provided.");
+            return;
+        };
+        let Some(target) = bookmarks::find(&app.bookmarks, name).cloned() else {
+            app.set_output("Bookmark", &format!("Error: No bookmark named {}.", name));
+            return;
+        };
+        if !target.is_dir() {
+            app.set_output(
+                "Bookmark",
+                &format!("Error: {} no longer exists.", target.to_string_lossy()),
+            );
+            return;
         }
+        app.mode_bookmarks = false;
+        app.append_cwd(&target);
+        app.update_listing();
+        app.update_results();
+        app.focus_index = 0;
     }
 
-    // Helper to get command string from CmdName
-    pub fn get_cmd(cmd_list: &cmd_data::CmdList, name: &cmd_data::CmdName) -> String {
-        get_cmd_data(cmd_list, name).cmd.to_string()
+    // Open a new tab in the current directory
+    pub fn tab_new(app: &mut App, _args: Vec<&str>) {
+        let index = app.tab_index + 1;
+        app.tabs.insert(index, Tab::new(app.cwd.clone()));
+        app.switch_tab(index);
     }
 
-    pub fn make_cmd_list() -> CmdList {
-        let mut map = HashMap::new();
-        map.insert(
-            CmdName::Exit,
-            CmdData {
-                fname: "Exit",
-                description: "Exit the application",
-                cmd: "exit",
-                vis_hidden: false,
-                params: vec![],
-                on_sel: false,
-                op: cmd::exit,
-            },
-        );
-        map.insert(
-            CmdName::Home,
-            CmdData {
-                fname: "Home",
-                description: "Go to your home directory",
-                cmd: "home",
-                vis_hidden: false,
-                params: vec![],
-                on_sel: false,
-                op: cmd::home,
-            },
-        );
-        map.insert(
-            CmdName::CurUp,
-            CmdData {
-                fname: "Cursor Up",
-                description: "Move selection cursor up",
-                cmd: "cur-up",
-                vis_hidden: true,
-                params: vec![],
-                on_sel: false,
-                op: cmd::cur_up,
-            },
-        );
-        map.insert(
-            CmdName::CurDown,
-            CmdData {
+    pub fn tab_close(app: &mut App, _args: Vec<&str>) {
+        if app.tabs.len() == 1 {
+            app.set_output("Tabs", "Can't close the last tab.");
+            return;
+        }
+        app.tabs.remove(app.tab_index);
+        app.load_tab(app.tab_index.min(app.tabs.len() - 1));
+    }
+
+    pub fn tab_next(app: &mut App, _args: Vec<&str>) {
+        app.switch_tab((app.tab_index + 1) % app.tabs.len());
+    }
+
+    pub fn tab_prev(app: &mut App, _args: Vec<&str>) {
+        app.switch_tab((app.tab_index + app.tabs.len() - 1) % app.tabs.len());
+    }
+
+    pub fn input_clear(app: &mut App, _args: Vec<&str>) {
+        app.search_buf.clear();
+        app.command_input.clear();
+        app.update_results();
+        app.focus_index = 0;
+    }
+
+    pub fn shell_quick(app: &mut App, args: Vec<&str>) {
+        // Join args into a single command string
+        let mut shell_cmd = args[0..].join(" ");
+
+        // Replace variables
+        shell_cmd = app.replace_shell_vars(shell_cmd);
+
+        // Add the cwd prefix
+        shell_cmd = format!("cd '{}' && {}", app.cwd.to_str().unwrap(), shell_cmd);
+
+        // Run the command
+        log!("Running shell command: {}", shell_cmd);
+        let out: String;
+        match Command::new(app.user_shell.as_str())
+            .arg("-c")
+            .arg(shell_cmd)
+            .output()
+        {
+            Ok(output) => {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                out = format!("{}{}", stdout, stderr);
+            }
+            Err(e) => {
+                out = format!("Failed to run command: {}", e);
+            }
+        }
+        if !out.is_empty() {
+            app.set_output("Shell", &out);
+        }
+        app.update_listing();
+        app.update_results();
+    }
+
+    pub fn shell_full(app: &mut App, _args: Vec<&str>) {
+        let shell = env::var("SHELL").unwrap_or("/bin/sh".to_string());
+        // Set the real program cwd
+        env::set_current_dir(&app.cwd).unwrap_or(());
+        log!("Opening shell: {}", shell);
+        util::cls();
+        match Command::new(shell).status() {
+            Ok(_) => {
+                app.set_output("Shell", "Shell closed.");
+            }
+            Err(e) => {
+                app.set_output("Shell", &format!("Failed to open shell: {}", e));
+            }
+        }
+        util::cls();
+        app.term_clear = true;
+    }
+
+    pub fn config_init(app: &mut App, _args: Vec<&str>) {
+        // Write the default files to the config directory
+        let config_path = cfg::Config::get_path();
+        let colors_path = cs::Colors::get_path();
+        let kb_path = kb::get_path();
+        let shell_cmds_path = shell_cmds::get_path();
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+        fs::create_dir_all(colors_path.parent().unwrap()).unwrap();
+        fs::create_dir_all(kb_path.parent().unwrap()).unwrap();
+        fs::create_dir_all(shell_cmds_path.parent().unwrap()).unwrap();
+        let mut output_text = String::new();
+        match fs::write(&config_path, cfg::DEFAULT) {
+            Ok(_) => {
+                output_text += &format!("Created config file: {}\n", config_path.to_str().unwrap());
+            }
+            Err(e) => {
+                output_text += &format!(
+                    "Failed to create config file: {} ({})\n",
+                    config_path.to_str().unwrap(),
+                    e
+                );
+            }
+        }
+        match fs::write(&colors_path, cs::DEFAULT) {
+            Ok(_) => {
+                output_text += &format!("Created colors file: {}\n", colors_path.to_str().unwrap());
+            }
+            Err(e) => {
+                output_text += &format!(
+                    "Failed to create colors file: {} ({})\n",
+                    colors_path.to_str().unwrap(),
+                    e
+                );
+            }
+        }
+        match fs::write(&kb_path, kb::DEFAULT) {
+            Ok(_) => {
+                output_text += &format!("Created keybinds file: {}\n", kb_path.to_str().unwrap());
+            }
+            Err(e) => {
+                output_text += &format!(
+                    "Failed to create keybinds file: {} ({})\n",
+                    kb_path.to_str().unwrap(),
+                    e
+                );
+            }
+        }
+        match fs::write(&shell_cmds_path, shell_cmds::DEFAULT) {
+            Ok(_) => {
+                output_text += &format!(
+                    "Created shell commands file: {}\n",
+                    shell_cmds_path.to_str().unwrap()
+                );
+            }
+            Err(e) => {
+                output_text += &format!(
+                    "Failed to create shell commands file: {} ({})\n",
+                    shell_cmds_path.to_str().unwrap(),
+                    e
+                );
+            }
+        }
+        app.set_output("Config Init", &output_text);
+    }
+
+    pub fn config_clear(app: &mut App, _args: Vec<&str>) {
+        let config_path = cfg::Config::get_path();
+        let colors_path = cs::Colors::get_path();
+        let kb_path = kb::get_path();
+        let shell_cmds_path = shell_cmds::get_path();
+        let mut output_text = String::new();
+        match fs::remove_file(&config_path) {
+            Ok(_) => {
+                output_text += &format!("Removed config file: {}\n", config_path.to_str().unwrap());
+            }
+            Err(e) => {
+                output_text += &format!(
+                    "Failed to remove config file: {} ({})\n",
+                    config_path.to_str().unwrap(),
+                    e
+                );
+            }
+        }
+        match fs::remove_file(&colors_path) {
+            Ok(_) => {
+                output_text += &format!("Removed colors file: {}\n", colors_path.to_str().unwrap());
+            }
+            Err(e) => {
+                output_text += &format!(
+                    "Failed to remove colors file: {} ({})\n",
+                    colors_path.to_str().unwrap(),
+                    e
+                );
+            }
+        }
+        match fs::remove_file(&kb_path) {
+            Ok(_) => {
+                output_text += &format!("Removed keybinds file: {}\n", kb_path.to_str().unwrap());
+            }
+            Err(e) => {
+                output_text += &format!(
+                    "Failed to remove keybinds file: {} ({})\n",
+                    kb_path.to_str().unwrap(),
+                    e
+                );
+            }
+        }
+        match fs::remove_file(&shell_cmds_path) {
+            Ok(_) => {
+                output_text += &format!(
+                    "Removed shell commands file: {}\n",
+                    shell_cmds_path.to_str().unwrap()
+                );
+            }
+            Err(e) => {
+                output_text += &format!(
+                    "Failed to remove shell commands file: {} ({})\n",
+                    shell_cmds_path.to_str().unwrap(),
+                    e
+                );
+            }
+        }
+        app.set_output("Config Clear", &output_text);
+    }
+
+    pub fn config_goto(app: &mut App, _args: Vec<&str>) {
+        let config_path = cfg::Config::get_path();
+        app.append_cwd(&config_path.parent().unwrap().to_path_buf());
+        app.update_listing();
+        app.update_results();
+    }
+
+    pub fn dbg_clear_preview(app: &mut App, _args: Vec<&str>) {
+        app.term_clear = true;
+    }
+}
+
+// Command metadata
+mod cmd_data {
+    use std::collections::HashMap;
+
+    use crate::{App, cmd, cmd_data};
+
+    #[derive(Hash, Eq, PartialEq, Debug, Clone, PartialOrd, Ord)]
+    pub enum CmdName {
+        Exit,
+        Home,
+        CurUp,
+        CurDown,
+        DirUp,
+        DirBack,
+        DirForward,
+        History,
+        DirReload,
+        Explode,
+        Enter,
+        CmdWinToggle,
+        CmdFinderToggle,
+        CmdList,
+        CmdListDump,
+        OutputWinToggle,
+        OutputWinShow,
+        OutputWinHide,
+        Sel,
+        SelClear,
+        SelShow,
+        SelSave,
+        Copy,
+        Delete,
+        DeletePermanent,
+        TrashList,
+        TrashRestore,
+        TrashEmpty,
+        Undo,
+        Redo,
+        Jobs,
+        Move,
+        ClipPath,
+        MenuBack,
+        Log,
+        LogClear,
+        SecUp,
+        SecDown,
+        KeybindsShow,
+        DbgClear,
+        Edit,
+        OsOpen,
+        MkDir,
+        Rename,
+        BulkRename,
+        RenamePattern,
+        Touch,
+        NewFromTemplate,
+        Extract,
+        Compress,
+        GoTo,
+        HiddenToggle,
+        LongToggle,
+        HexToggle,
+        ContentSearch,
+        MatchMode,
+        Sort,
+        SortDirsFirst,
+        Jump,
+        JumpImport,
+        JumpPrune,
+        Bookmarks,
+        BookmarkAdd,
+        BookmarkDel,
+        GotoBookmark,
+        TabNew,
+        TabClose,
+        TabNext,
+        TabPrev,
+        InputClear,
+        ShellQuick,
+        ShellFull,
+        ConfigInit,
+        ConfigClear,
+        ConfigGoto,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct CmdData {
+        pub fname: &'static str,
+        pub description: &'static str,
+        pub cmd: &'static str,
+        pub vis_hidden: bool, // Hidden from visual cmd selection
+        pub params: Vec<&'static str>,
+        pub on_sel: bool, // Operates on multi selection
+        pub op: fn(&mut App, Vec<&str>) -> (),
+    }
+    pub type CmdList = HashMap<CmdName, CmdData>;
+    pub fn cmd_name_from_str(
+        cmd_list: &HashMap<CmdName, cmd_data::CmdData>,
+        cmd: &str,
+    ) -> Option<cmd_data::CmdName> {
+        for (name, data) in cmd_list.iter() {
+            if data.cmd == cmd {
+                return Some(name.clone());
+            }
+        }
+        None
+    }
+    pub fn get_cmd_data(
+        cmd_list: &cmd_data::CmdList,
+        name: &cmd_data::CmdName,
+    ) -> cmd_data::CmdData {
+        match cmd_list.get(name) {
+            Some(data) => data.clone(),
+            None => panic!("Command not found: {:?}", name),
+        }
+    }
+
+    // Helper to get command string from CmdName
+    pub fn get_cmd(cmd_list: &cmd_data::CmdList, name: &cmd_data::CmdName) -> String {
+        get_cmd_data(cmd_list, name).cmd.to_string()
+    }
+
+    pub fn make_cmd_list() -> CmdList {
+        let mut map = HashMap::new();
+        map.insert(
+            CmdName::Exit,
+            CmdData {
+                fname: "Exit",
+                description: "Exit the application",
+                cmd: "exit",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::exit,
+            },
+        );
+        map.insert(
+            CmdName::Home,
+            CmdData {
+                fname: "Home",
+                description: "Go to your home directory",
+                cmd: "home",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::home,
+            },
+        );
+        map.insert(
+            CmdName::CurUp,
+            CmdData {
+                fname: "Cursor Up",
+                description: "Move selection cursor up",
+                cmd: "cur-up",
+                vis_hidden: true,
+                params: vec![],
+                on_sel: false,
+                op: cmd::cur_up,
+            },
+        );
+        map.insert(
+            CmdName::CurDown,
+            CmdData {
                 fname: "Cursor Down",
                 description: "Move selection cursor down",
                 cmd: "cur-down",
@@ -1394,7 +2604,7 @@ mod cmd_data {
             CmdName::DirBack,
             CmdData {
                 fname: "Directory Back (cd -)",
-                description: "Go back to the most recent working directory",
+                description: "Go back to the previous directory in this session",
                 cmd: "dir-back",
                 vis_hidden: false,
                 params: vec![],
@@ -1402,6 +2612,30 @@ mod cmd_data {
                 op: cmd::dir_back,
             },
         );
+        map.insert(
+            CmdName::DirForward,
+            CmdData {
+                fname: "Directory Forward",
+                description: "Go forward again after going back",
+                cmd: "dir-forward",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::dir_forward,
+            },
+        );
+        map.insert(
+            CmdName::History,
+            CmdData {
+                fname: "History",
+                description: "Toggle picking from the directories visited this session",
+                cmd: "history",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::history,
+            },
+        );
         map.insert(
             CmdName::DirReload,
             CmdData {
@@ -1523,75 +2757,159 @@ mod cmd_data {
             },
         );
         map.insert(
-            CmdName::Sel,
+            CmdName::Sel,
+            CmdData {
+                fname: "Select Item Toggle",
+                description: "Toggle selection of the item under the cursor",
+                cmd: "sel",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: true,
+                op: cmd::sel,
+            },
+        );
+        map.insert(
+            CmdName::SelClear,
+            CmdData {
+                fname: "Selection Clear",
+                description: "Clear the current selection of files and directories",
+                cmd: "sel-clear",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: true,
+                op: cmd::sel_clear,
+            },
+        );
+        map.insert(
+            CmdName::SelShow,
+            CmdData {
+                fname: "Selection Show",
+                description: "Show the current selection of files and directories in the output window",
+                cmd: "sel-show",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: true,
+                op: cmd::sel_show,
+            },
+        );
+        map.insert(
+            CmdName::SelSave,
+            CmdData {
+                fname: "Selection Save",
+                description: "Save the current selection of files and directories to file",
+                cmd: "sel-save",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: true,
+                op: cmd::sel_save,
+            },
+        );
+        map.insert(
+            CmdName::Copy,
+            CmdData {
+                fname: "Copy Selection",
+                description: "Copy the current selection of files and directories to the current directory",
+                cmd: "copy",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: true,
+                op: cmd::sel_copy,
+            },
+        );
+        map.insert(
+            CmdName::Delete,
+            CmdData {
+                fname: "Delete Selection",
+                description: "Move all currently selected files and directories to the trash",
+                cmd: "delete",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: true,
+                op: cmd::sel_delete,
+            },
+        );
+        map.insert(
+            CmdName::DeletePermanent,
             CmdData {
-                fname: "Select Item Toggle",
-                description: "Toggle selection of the item under the cursor",
-                cmd: "sel",
+                fname: "Delete Selection Permanently",
+                description: "Permanently delete all currently selected files and directories, bypassing the trash",
+                cmd: "delete-permanent",
                 vis_hidden: false,
                 params: vec![],
                 on_sel: true,
-                op: cmd::sel,
+                op: cmd::sel_delete_permanent,
             },
         );
         map.insert(
-            CmdName::SelClear,
+            CmdName::TrashList,
             CmdData {
-                fname: "Selection Clear",
-                description: "Clear the current selection of files and directories",
-                cmd: "sel-clear",
+                fname: "Trash List",
+                description: "List the items in the trash",
+                cmd: "trash-list",
                 vis_hidden: false,
                 params: vec![],
-                on_sel: true,
-                op: cmd::sel_clear,
+                on_sel: false,
+                op: cmd::trash_list,
             },
         );
         map.insert(
-            CmdName::SelShow,
+            CmdName::TrashRestore,
             CmdData {
-                fname: "Selection Show",
-                description: "Show the current selection of files and directories in the output window",
-                cmd: "sel-show",
+                fname: "Trash Restore",
+                description: "Restore an item from the trash to its original location",
+                cmd: "trash-restore",
+                vis_hidden: false,
+                params: vec!["name"],
+                on_sel: false,
+                op: cmd::trash_restore,
+            },
+        );
+        map.insert(
+            CmdName::TrashEmpty,
+            CmdData {
+                fname: "Trash Empty",
+                description: "Permanently delete everything in the trash",
+                cmd: "trash-empty",
                 vis_hidden: false,
                 params: vec![],
-                on_sel: true,
-                op: cmd::sel_show,
+                on_sel: false,
+                op: cmd::trash_empty,
             },
         );
         map.insert(
-            CmdName::SelSave,
+            CmdName::Undo,
             CmdData {
-                fname: "Selection Save",
-                description: "Save the current selection of files and directories to file",
-                cmd: "sel-save",
+                fname: "Undo",
+                description: "Undo the last copy, move, rename, mkdir or delete",
+                cmd: "undo",
                 vis_hidden: false,
                 params: vec![],
-                on_sel: true,
-                op: cmd::sel_save,
+                on_sel: false,
+                op: cmd::undo,
             },
         );
         map.insert(
-            CmdName::Copy,
+            CmdName::Redo,
             CmdData {
-                fname: "Copy Selection",
-                description: "Copy the current selection of files and directories to the current directory",
-                cmd: "copy",
+                fname: "Redo",
+                description: "Redo the last undone file operation",
+                cmd: "redo",
                 vis_hidden: false,
                 params: vec![],
-                on_sel: true,
-                op: cmd::sel_copy,
+                on_sel: false,
+                op: cmd::redo,
             },
         );
         map.insert(
-            CmdName::Delete,
+            CmdName::Jobs,
             CmdData {
-                fname: "Delete Selection",
-                description: "Delete all currently selected files and directories",
-                cmd: "delete",
+                fname: "Jobs",
+                description: "Show queued and finished jobs to cancel, pause or retry them",
+                cmd: "jobs",
                 vis_hidden: false,
                 params: vec![],
-                on_sel: true,
-                op: cmd::sel_delete,
+                on_sel: false,
+                op: cmd::jobs,
             },
         );
         map.insert(
@@ -1738,6 +3056,90 @@ mod cmd_data {
                 op: cmd::mk_dir,
             },
         );
+        map.insert(
+            CmdName::Rename,
+            CmdData {
+                fname: "Rename",
+                description: "Rename the focused file or directory",
+                cmd: "rename",
+                vis_hidden: false,
+                params: vec!["new_name"],
+                on_sel: false,
+                op: cmd::rename,
+            },
+        );
+        map.insert(
+            CmdName::BulkRename,
+            CmdData {
+                fname: "Bulk Rename",
+                description: "Rename the selection (or the whole listing) in $EDITOR",
+                cmd: "bulk-rename",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::bulk_rename,
+            },
+        );
+        map.insert(
+            CmdName::RenamePattern,
+            CmdData {
+                fname: "Rename Pattern",
+                description: "Rename the selection (or the listing) with a regex, previewed while typing",
+                cmd: "rename-pattern",
+                vis_hidden: false,
+                params: vec!["from", "to"],
+                on_sel: false,
+                op: cmd::rename_pattern,
+            },
+        );
+        map.insert(
+            CmdName::Touch,
+            CmdData {
+                fname: "Create File",
+                description: "Create a new file, missing parent directories are created too",
+                cmd: "touch",
+                vis_hidden: false,
+                params: vec!["file_name"],
+                on_sel: false,
+                op: cmd::touch,
+            },
+        );
+        map.insert(
+            CmdName::NewFromTemplate,
+            CmdData {
+                fname: "New From Template",
+                description: "Create a file or directory from the templates folder in the config directory",
+                cmd: "new-from-template",
+                vis_hidden: false,
+                params: vec!["template", "name"],
+                on_sel: false,
+                op: cmd::new_from_template,
+            },
+        );
+        map.insert(
+            CmdName::Extract,
+            CmdData {
+                fname: "Extract Archives",
+                description: "Extract the selected archives into new folders in the current directory",
+                cmd: "extract",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: true,
+                op: cmd::extract,
+            },
+        );
+        map.insert(
+            CmdName::Compress,
+            CmdData {
+                fname: "Compress Selection",
+                description: "Create an archive of the selection, the format comes from the name (zip, tar, tar.gz, tar.xz, tar.zst)",
+                cmd: "compress",
+                vis_hidden: false,
+                params: vec!["archive_name"],
+                on_sel: true,
+                op: cmd::compress,
+            },
+        );
         map.insert(
             CmdName::GoTo,
             CmdData {
@@ -1762,6 +3164,210 @@ mod cmd_data {
                 op: cmd::hidden_toggle,
             },
         );
+        map.insert(
+            CmdName::LongToggle,
+            CmdData {
+                fname: "Long Toggle",
+                description: "Toggle showing size, modified time, permissions and owner in the listing",
+                cmd: "long-toggle",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::long_toggle,
+            },
+        );
+        map.insert(
+            CmdName::HexToggle,
+            CmdData {
+                fname: "Hex Toggle",
+                description: "Toggle the focused file between the hex dump and text preview",
+                cmd: "hex-toggle",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::hex_toggle,
+            },
+        );
+        map.insert(
+            CmdName::ContentSearch,
+            CmdData {
+                fname: "Content Search",
+                description: "Toggle searching the contents of files (or start the search with /)",
+                cmd: "content-search",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::content_search,
+            },
+        );
+        map.insert(
+            CmdName::MatchMode,
+            CmdData {
+                fname: "Match Mode",
+                description: "Cycle how the search matches names (fuzzy, substring, prefix, regex, glob)",
+                cmd: "match-mode",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::match_mode,
+            },
+        );
+        map.insert(
+            CmdName::Jump,
+            CmdData {
+                fname: "Jump",
+                description: "Toggle fuzzy searching your most visited directories",
+                cmd: "jump",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::jump,
+            },
+        );
+        map.insert(
+            CmdName::JumpImport,
+            CmdData {
+                fname: "Jump Import",
+                description: "Import visited directories from zoxide and/or autojump",
+                cmd: "jump-import",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::jump_import,
+            },
+        );
+        map.insert(
+            CmdName::Sort,
+            CmdData {
+                fname: "Sort",
+                description: "Cycle how the listing is sorted, or sort by name, size, mtime, ext or type",
+                cmd: "sort",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::sort,
+            },
+        );
+        map.insert(
+            CmdName::SortDirsFirst,
+            CmdData {
+                fname: "Sort Directories First",
+                description: "Toggle listing directories before files",
+                cmd: "sort-dirs-first",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::sort_dirs_first,
+            },
+        );
+        map.insert(
+            CmdName::Bookmarks,
+            CmdData {
+                fname: "Bookmarks",
+                description: "Toggle picking from your bookmarks",
+                cmd: "bookmarks",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::bookmarks,
+            },
+        );
+        map.insert(
+            CmdName::BookmarkAdd,
+            CmdData {
+                fname: "Bookmark Add",
+                description: "Bookmark the current directory under a name",
+                cmd: "bookmark-add",
+                vis_hidden: false,
+                params: vec!["name"],
+                on_sel: false,
+                op: cmd::bookmark_add,
+            },
+        );
+        map.insert(
+            CmdName::BookmarkDel,
+            CmdData {
+                fname: "Bookmark Delete",
+                description: "Remove a bookmark by name (or the focused one in the bookmark list)",
+                cmd: "bookmark-del",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::bookmark_del,
+            },
+        );
+        map.insert(
+            CmdName::GotoBookmark,
+            CmdData {
+                fname: "Go To Bookmark",
+                description: "Go to the directory of a bookmark",
+                cmd: "goto-bookmark",
+                vis_hidden: false,
+                params: vec!["name"],
+                on_sel: false,
+                op: cmd::goto_bookmark,
+            },
+        );
+        map.insert(
+            CmdName::TabNew,
+            CmdData {
+                fname: "Tab New",
+                description: "Open a new tab in the current directory",
+                cmd: "tab-new",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::tab_new,
+            },
+        );
+        map.insert(
+            CmdName::TabClose,
+            CmdData {
+                fname: "Tab Close",
+                description: "Close the current tab",
+                cmd: "tab-close",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::tab_close,
+            },
+        );
+        map.insert(
+            CmdName::TabNext,
+            CmdData {
+                fname: "Tab Next",
+                description: "Switch to the next tab",
+                cmd: "tab-next",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::tab_next,
+            },
+        );
+        map.insert(
+            CmdName::TabPrev,
+            CmdData {
+                fname: "Tab Previous",
+                description: "Switch to the previous tab",
+                cmd: "tab-prev",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::tab_prev,
+            },
+        );
+        map.insert(
+            CmdName::JumpPrune,
+            CmdData {
+                fname: "Jump Prune",
+                description: "Remove directories which no longer exist from the jump list",
+                cmd: "jump-prune",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::jump_prune,
+            },
+        );
         map.insert(
             CmdName::InputClear,
             CmdData {
@@ -1798,668 +3404,4642 @@ mod cmd_data {
                 op: cmd::shell_full,
             },
         );
-        map.insert(
-            CmdName::ConfigInit,
-            CmdData {
-                fname: "Config Init",
-                description: "Initialize the configuration files with defaults",
-                cmd: "config-init",
-                vis_hidden: false,
-                params: vec![],
-                on_sel: false,
-                op: cmd::config_init,
-            },
+        map.insert(
+            CmdName::ConfigInit,
+            CmdData {
+                fname: "Config Init",
+                description: "Initialize the configuration files with defaults",
+                cmd: "config-init",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::config_init,
+            },
+        );
+        map.insert(
+            CmdName::ConfigClear,
+            CmdData {
+                fname: "Config Clear",
+                description: "Clear (delete) the configuration files",
+                cmd: "config-clear",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::config_clear,
+            },
+        );
+        map.insert(
+            CmdName::ConfigGoto,
+            CmdData {
+                fname: "Config Goto",
+                description: "Go to the configuration directory",
+                cmd: "config-goto",
+                vis_hidden: false,
+                params: vec![],
+                on_sel: false,
+                op: cmd::config_goto,
+            },
+        );
+
+        map
+    }
+}
+
+// Color scheme management
+mod cs {
+    use crate::log;
+    use ratatui::style::Color;
+    use std::fs;
+
+    const FILE_NAME: &str = "colors.txt";
+    pub const DEFAULT: &str = r#"
+#
+# Default color
+#
+
+name           default
+search_border  green
+preview_border yellow
+listing_border blue
+file           green
+dir            blue
+command        cyan
+executable     lightred
+shortcut       yellow
+image          lightmagenta
+header         lightblue
+info           yellow
+tip            green
+warning        yellow
+error          red
+ok             green
+hi             white
+dim            gray
+misc           white
+match          lightyellow
+"#;
+    // Color scheme struct
+    #[derive(Clone)]
+    pub struct Colors {
+        pub name: String,
+        pub search_border: Color,
+        pub preview_border: Color,
+        pub listing_border: Color,
+        pub file: Color,
+        pub dir: Color,
+        pub command: Color,
+        pub executable: Color,
+        pub shortcut: Color,
+        pub image: Color,
+        pub info: Color,
+        pub tip: Color,
+        pub warning: Color,
+        pub error: Color,
+        pub ok: Color,
+        pub header: Color,
+        pub hi: Color,
+        pub dim: Color,
+        pub misc: Color,
+        pub matched: Color, // Characters which matched the search
+    }
+    impl Colors {
+        pub fn new() -> Self {
+            Self {
+                name: "default".to_string(),
+                search_border: Color::White,
+                preview_border: Color::White,
+                listing_border: Color::White,
+                file: Color::White,
+                dir: Color::White,
+                command: Color::White,
+                executable: Color::White,
+                shortcut: Color::White,
+                image: Color::White,
+                info: Color::White,
+                tip: Color::White,
+                warning: Color::White,
+                error: Color::White,
+                ok: Color::White,
+                header: Color::White,
+                hi: Color::White,
+                dim: Color::White,
+                misc: Color::White,
+                matched: Color::White,
+            }
+        }
+        pub fn from_str(s: &str) -> Color {
+            match s.to_lowercase().as_str() {
+                "black" => Color::Black,
+                "red" => Color::Red,
+                "green" => Color::Green,
+                "yellow" => Color::Yellow,
+                "blue" => Color::Blue,
+                "magenta" => Color::Magenta,
+                "cyan" => Color::Cyan,
+                "gray" => Color::Gray,
+                "darkgray" => Color::DarkGray,
+                "lightred" => Color::LightRed,
+                "lightgreen" => Color::LightGreen,
+                "lightyellow" => Color::LightYellow,
+                "lightblue" => Color::LightBlue,
+                "lightmagenta" => Color::LightMagenta,
+                "lightcyan" => Color::LightCyan,
+                "white" => Color::White,
+                _ => Color::White,
+            }
+        }
+
+        pub fn get_path() -> std::path::PathBuf {
+            let cs_path = dirs::config_dir()
+                .unwrap_or(std::env::current_dir().unwrap())
+                .join(crate::APP_NAME)
+                .join(FILE_NAME);
+            cs_path
+        }
+
+        pub fn make_list_auto() -> Colors {
+            // Read colors.txt
+            let colors = match fs::read_to_string(crate::APP_NAME.to_string() + FILE_NAME) {
+                Ok(content) => content,
+                Err(_) => {
+                    log!("colors.txt not found, using default colorscheme");
+                    return Colors::make_list(DEFAULT);
+                }
+            };
+            Colors::make_list(&colors)
+        }
+
+        fn make_list(colors_str: &str) -> Colors {
+            let mut colors = Colors::new();
+            for line in colors_str.lines() {
+                // Ignore comments
+                if line.starts_with('#') || line.trim().is_empty() {
+                    continue;
+                }
+                // Trim whitespace
+                let line = line.trim();
+                let split = line.split_whitespace().collect::<Vec<&str>>();
+                if split.len() != 2 {
+                    continue;
+                }
+                let key = split[0];
+                let value = split[1];
+                match key {
+                    "name" => colors.name = value.to_string(),
+                    "search_border" => {
+                        colors.search_border = Colors::from_str(value);
+                    }
+                    "preview_border" => {
+                        colors.preview_border = Colors::from_str(value);
+                    }
+                    "listing_border" => {
+                        colors.listing_border = Colors::from_str(value);
+                    }
+                    "file" => {
+                        colors.file = Colors::from_str(value);
+                    }
+                    "dir" => {
+                        colors.dir = Colors::from_str(value);
+                    }
+                    "command" => {
+                        colors.command = Colors::from_str(value);
+                    }
+                    "executable" => {
+                        colors.executable = Colors::from_str(value);
+                    }
+                    "shortcut" => {
+                        colors.shortcut = Colors::from_str(value);
+                    }
+                    "image" => {
+                        colors.image = Colors::from_str(value);
+                    }
+                    "info" => {
+                        colors.info = Colors::from_str(value);
+                    }
+                    "tip" => {
+                        colors.tip = Colors::from_str(value);
+                    }
+                    "warning" => {
+                        colors.warning = Colors::from_str(value);
+                    }
+                    "error" => {
+                        colors.error = Colors::from_str(value);
+                    }
+                    "ok" => {
+                        colors.ok = Colors::from_str(value);
+                    }
+                    "header" => {
+                        colors.header = Colors::from_str(value);
+                    }
+                    "hi" => {
+                        colors.hi = Colors::from_str(value);
+                    }
+                    "dim" => {
+                        colors.dim = Colors::from_str(value);
+                    }
+                    "misc" => {
+                        colors.misc = Colors::from_str(value);
+                    }
+                    "match" => {
+                        colors.matched = Colors::from_str(value);
+                    }
+                    _ => {}
+                }
+            }
+            colors
+        }
+    }
+}
+
+// Keybinding management
+mod kb {
+    use super::cmd_data;
+    use super::cmd_data::CmdName;
+    use crate::{APP_NAME, log};
+    use crossterm::event::{KeyCode, KeyModifiers};
+    use std::{env, fs, path::PathBuf};
+
+    const FILE_NAME: &str = "keybinds.txt";
+    pub const DEFAULT: &str = r#"
+#
+# Default keybinds
+#
+
+exit           esc
+exit           ctrl-q
+home           alt-g
+cur-up         up
+cur-up         ctrl-k
+cur-down       down
+cur-down       ctrl-j
+sel            tab
+dir-up         ctrl-h
+dir-up         left
+dir-back       ctrl-u
+explode        ctrl-x
+edit           ctrl-e
+jump           ctrl-g
+enter          enter
+enter          right
+enter          ctrl-l
+cmd-win        ctrl-w
+cmd-find       ctrl-t
+cmd-list       ctrl-i
+sec-up         alt-k
+sec-up         ctrl-up
+sec-down       alt-j
+sec-down       ctrl-down
+input-clear    ctrl-z
+shell          ctrl-s
+os-open        ctrl-o
+hidden-toggle  alt-h
+tab-new        alt-t
+tab-close      alt-w
+tab-next       alt-.
+tab-prev       alt-,
+"#;
+    #[derive(Clone)]
+    pub struct KeyBind {
+        pub modifiers: KeyModifiers,
+        pub code: KeyCode,
+        pub command: CmdName,
+        pub args: Vec<String>, // Given between the command and the key
+    }
+    impl KeyBind {
+        fn new(
+            modifiers: KeyModifiers,
+            code: KeyCode,
+            command: CmdName,
+            args: Vec<String>,
+        ) -> Self {
+            Self {
+                modifiers,
+                code,
+                command,
+                args,
+            }
+        }
+    }
+    pub type KeyBindList = Vec<KeyBind>;
+
+    // Unused
+    // pub fn to_strings(kb: &KeyBind) -> (String, String) {
+    //     let modifier = match kb.modifiers {
+    //         KeyModifiers::ALT => "alt",
+    //         KeyModifiers::CONTROL => "ctrl",
+    //         KeyModifiers::SHIFT => "shift",
+    //         KeyModifiers::NONE => "none",
+    //         _ => "UNKNOWN",
+    //     };
+    //     let code_str = match &kb.code {
+    //         KeyCode::Char(c) => c.to_string(),
+    //         _ => kb.code.to_string(),
+    //     };
+    //     (modifier.to_string(), code_str.to_lowercase())
+    // }
+
+    // Short string like "ctrl-a"
+    pub fn to_string_short(kb: &KeyBind) -> String {
+        let modifier = match kb.modifiers {
+            KeyModifiers::ALT => "alt",
+            KeyModifiers::CONTROL => "ctrl",
+            KeyModifiers::SHIFT => "shift",
+            KeyModifiers::NONE => "",
+            _ => "UNKNOWN",
+        };
+        if modifier.is_empty() {
+            return format!("{}", kb.code.to_string().to_lowercase());
+        }
+        return format!("{}-{}", modifier, kb.code.to_string().to_lowercase());
+    }
+
+    // Full string with command name
+    pub fn to_string_full(cmd_list: &cmd_data::CmdList, kb: &KeyBind) -> String {
+        let mut cmd = cmd_data::get_cmd(cmd_list, &kb.command);
+        for arg in kb.args.iter() {
+            cmd += &format!(" {}", arg);
+        }
+        return format!("{:<12} {}\n", cmd, to_string_short(kb));
+    }
+
+    pub fn find_by_cmd(keybinds: &KeyBindList, cmd: &cmd_data::CmdName) -> Option<KeyBind> {
+        for kb in keybinds.iter() {
+            if &kb.command == cmd {
+                return Some(kb.clone());
+            }
+        }
+        None
+    }
+
+    pub fn get_path() -> PathBuf {
+        let kb_path = dirs::config_dir()
+            .unwrap_or(env::current_dir().unwrap())
+            .join(APP_NAME)
+            .join(FILE_NAME);
+        kb_path
+    }
+
+    pub fn make_list_auto() -> KeyBindList {
+        // Read keybinds.txt
+        let keybinds = match fs::read_to_string(get_path()) {
+            Ok(content) => content,
+            Err(_) => {
+                log!("{} not found, using default keybinds", FILE_NAME);
+                return make_list(DEFAULT);
+            }
+        };
+        make_list(&keybinds)
+    }
+
+    fn make_list(keybinds_str: &str) -> KeyBindList {
+        let mut list = KeyBindList::new();
+        let cmd_list = cmd_data::make_cmd_list();
+        for line in keybinds_str.lines() {
+            // Ignore comments
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            // Trim whitespace
+            let line = line.trim();
+            let split = line.split_whitespace().collect::<Vec<&str>>();
+            if split.len() < 2 {
+                log!("Invalid line in {}: {}", FILE_NAME, line);
+                continue;
+            }
+            // Anything between the command and the key is passed as args
+            let cmd = split[0];
+            let combo = split[split.len() - 1];
+            let args = split[1..split.len() - 1]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect::<Vec<String>>();
+            let (modifier, code) = if combo.contains('-') {
+                let combo_split = combo.splitn(2, '-').collect::<Vec<&str>>();
+                (combo_split[0], combo_split[1])
+            } else {
+                ("none", combo)
+            };
+            let cmd = match cmd_data::cmd_name_from_str(&cmd_list, cmd) {
+                Some(name) => name,
+                None => {
+                    log!("Unknown command in {}: {}", FILE_NAME, cmd);
+                    continue;
+                }
+            };
+            let modifiers = match modifier.to_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                "none" => KeyModifiers::NONE,
+                _ => {
+                    log!("Unknown modifier in {}: {}", FILE_NAME, modifier);
+                    continue;
+                }
+            };
+            let code = match code.to_lowercase().as_str() {
+                "enter" => KeyCode::Enter,
+                "esc" => KeyCode::Esc,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "space" => KeyCode::Char(' '),
+                c if c.len() == 1 => {
+                    let ch = c.chars().next().unwrap();
+                    KeyCode::Char(ch)
+                }
+                _ => {
+                    log!("Unknown key code in {}: {}", FILE_NAME, code);
+                    continue;
+                }
+            };
+            let keybind = KeyBind::new(modifiers, code, cmd, args);
+            list.push(keybind);
+        }
+        list
+    }
+}
+
+// General configuration management
+mod cfg {
+    use crate::{conflict, matching, sort};
+    use std::fs;
+
+    const FILE_NAME: &str = "config.txt";
+    pub const DEFAULT: &str = r#"
+#
+# Default configuration
+#
+
+# Command to run when you 'enter' on a file/directory
+cmd_on_enter     edit
+
+# How many items to show in a list - 0 = no limit
+list_limit       100
+
+# How many items can be searched at once - 0 = no limit
+find_limit       0
+
+# How many lines to preview - 0 = no limit
+preview_limit    100
+
+# Whether to force sixel image rendering (if terminal supports it)
+force_sixel      false
+
+# Maximum image width in characters
+max_image_width  80
+
+# Responsive breakpoint in characters
+responsive_break 96
+
+# Input polling interval in milliseconds
+# Higher value = lower CPU usage, lower value = more responsive input
+input_poll       10
+
+# Whether to show hidden files by default
+# show_hidden    true
+
+# What to do when a copy/move destination already exists
+# ask, overwrite, skip, rename or newer (overwrite if the source is newer)
+conflict_policy  ask
+
+# Whether copying follows symlinks (copies what they point to)
+# Otherwise symlinks are recreated as symlinks
+copy_follow_symlinks false
+
+# Whether copies keep the original owner and group (usually needs root)
+copy_preserve_owner  false
+# Both also apply to moves across filesystems, which copy and then delete
+
+# How the search matches names
+# fuzzy, substring, prefix, regex or glob
+match_mode       fuzzy
+
+# Case sensitivity of the search
+# smart (sensitive only if the search has uppercase letters), sensitive or insensitive
+match_case       smart
+
+# How listings are sorted
+# name (natural order), size, mtime, ext or type
+sort             name
+
+# asc or desc
+sort_order       asc
+
+# Whether directories are grouped before files
+sort_dirs_first  true
+
+# Whether the listing shows detail columns (toggle with long-toggle)
+long_listing     false
+
+# Which detail columns are shown, comma separated
+# size, mtime, perms and owner
+long_columns     size,mtime,perms,owner
+"#;
+    pub struct Config {
+        pub cmd_on_enter: String,
+        pub list_limit: u32,
+        pub find_limit: u32,
+        pub preview_limit: usize,
+        pub force_sixel: bool,
+        pub max_image_width: u16,
+        pub responsive_break: u16,
+        pub input_poll: u64,
+        pub show_hidden: bool,
+        pub conflict_policy: conflict::Policy,
+        pub copy_follow_symlinks: bool,
+        pub copy_preserve_owner: bool,
+        pub match_mode: matching::Mode,
+        pub match_case: matching::Case,
+        pub sort: sort::Sort,
+        pub long_listing: bool,
+        pub long_columns: Vec<Column>,
+    }
+
+    // Detail columns of the long listing
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub enum Column {
+        Size,
+        Modified,
+        Perms,
+        Owner,
+    }
+    impl Column {
+        pub fn from_str(s: &str) -> Option<Column> {
+            match s.to_lowercase().as_str() {
+                "size" => Some(Column::Size),
+                "mtime" => Some(Column::Modified),
+                "perms" => Some(Column::Perms),
+                "owner" => Some(Column::Owner),
+                _ => None,
+            }
+        }
+    }
+    impl Config {
+        pub fn new() -> Self {
+            Self {
+                cmd_on_enter: "edit".to_string(),
+                list_limit: 100,
+                find_limit: 0,
+                preview_limit: 100,
+                force_sixel: false,
+                max_image_width: 80,
+                responsive_break: 100,
+                input_poll: 10,
+                show_hidden: true,
+                conflict_policy: conflict::Policy::Ask,
+                copy_follow_symlinks: false,
+                copy_preserve_owner: false,
+                match_mode: matching::Mode::Fuzzy,
+                match_case: matching::Case::Smart,
+                sort: sort::Sort::new(),
+                long_listing: false,
+                long_columns: vec![Column::Size, Column::Modified, Column::Perms, Column::Owner],
+            }
+        }
+        pub fn get_path() -> std::path::PathBuf {
+            let cfg_path = dirs::config_dir()
+                .unwrap_or(std::env::current_dir().unwrap())
+                .join(crate::APP_NAME)
+                .join(FILE_NAME);
+            cfg_path
+        }
+        pub fn make_list_auto() -> Config {
+            // Read config.txt
+            let config = match fs::read_to_string(crate::APP_NAME.to_string() + FILE_NAME) {
+                Ok(content) => content,
+                Err(_) => {
+                    crate::log!("{} not found, using default configuration", FILE_NAME);
+                    return Config::make_list(DEFAULT);
+                }
+            };
+            Config::make_list(&config)
+        }
+        fn make_list(cfg_str: &str) -> Config {
+            let mut config = Config::new();
+            for line in cfg_str.lines() {
+                // Ignore comments
+                if line.starts_with('#') || line.trim().is_empty() {
+                    continue;
+                }
+                // Trim whitespace
+                let line = line.trim();
+                let split = line.split_whitespace().collect::<Vec<&str>>();
+                if split.len() != 2 {
+                    continue;
+                }
+                let key = split[0];
+                let value = split[1];
+                match key {
+                    "cmd_on_enter" => config.cmd_on_enter = value.to_string(),
+                    "list_limit" => {
+                        if let Ok(limit) = value.parse::<u32>() {
+                            config.list_limit = if limit == 0 { u32::MAX } else { limit };
+                        }
+                    }
+                    "find_limit" => {
+                        if let Ok(limit) = value.parse::<u32>() {
+                            config.find_limit = if limit == 0 { u32::MAX } else { limit };
+                        }
+                    }
+                    "preview_limit" => {
+                        if let Ok(limit) = value.parse::<usize>() {
+                            config.preview_limit = if limit == 0 { usize::MAX } else { limit };
+                        }
+                    }
+                    "force_sixel" => {
+                        if value.to_lowercase() == "true" {
+                            config.force_sixel = true;
+                        } else {
+                            config.force_sixel = false;
+                        }
+                    }
+                    "max_image_width" => {
+                        if let Ok(width) = value.parse::<u16>() {
+                            config.max_image_width = width;
+                        }
+                    }
+                    "responsive_break" => {
+                        if let Ok(breakpoint) = value.parse::<u16>() {
+                            config.responsive_break = breakpoint;
+                        }
+                    }
+                    "input_poll" => {
+                        if let Ok(poll) = value.parse::<u64>() {
+                            config.input_poll = poll;
+                        }
+                    }
+                    "show_hidden" => {
+                        if value.to_lowercase() == "true" {
+                            config.show_hidden = true;
+                        } else {
+                            config.show_hidden = false;
+                        }
+                    }
+                    "conflict_policy" => {
+                        if let Some(policy) = conflict::Policy::from_str(value) {
+                            config.conflict_policy = policy;
+                        }
+                    }
+                    "copy_follow_symlinks" => {
+                        config.copy_follow_symlinks = value.to_lowercase() == "true";
+                    }
+                    "copy_preserve_owner" => {
+                        config.copy_preserve_owner = value.to_lowercase() == "true";
+                    }
+                    "match_mode" => {
+                        if let Some(mode) = matching::Mode::from_str(value) {
+                            config.match_mode = mode;
+                        }
+                    }
+                    "match_case" => {
+                        if let Some(case) = matching::Case::from_str(value) {
+                            config.match_case = case;
+                        }
+                    }
+                    "sort" => {
+                        if let Some(key) = sort::Key::from_str(value) {
+                            config.sort.key = key;
+                        }
+                    }
+                    "sort_order" => {
+                        config.sort.desc = value.to_lowercase() == "desc";
+                    }
+                    "sort_dirs_first" => {
+                        config.sort.dirs_first = value.to_lowercase() == "true";
+                    }
+                    "long_listing" => {
+                        config.long_listing = value.to_lowercase() == "true";
+                    }
+                    "long_columns" => {
+                        config.long_columns =
+                            value.split(',').filter_map(Column::from_str).collect();
+                    }
+                    _ => {}
+                }
+            }
+            config
+        }
+    }
+}
+
+// Shell command management
+mod shell_cmds {
+    use crate::cmd_data;
+
+    const FILE_NAME: &str = "shell_cmds.txt";
+    pub const DEFAULT: &str = r#"
+#
+# Default shell commands
+#
+
+# A simple command
+ls -la
+# Ask for input (open prompt window)
+echo "you said" {ASK}
+# Echo the first selected file/directory
+echo $1
+# Create a zip archive of all selected files/directories
+zip -r archive.zip $...
+"#;
+
+    pub fn get_path() -> std::path::PathBuf {
+        let shell_cmds_path = dirs::config_dir()
+            .unwrap_or(std::env::current_dir().unwrap())
+            .join(crate::APP_NAME)
+            .join(FILE_NAME);
+        shell_cmds_path
+    }
+
+    pub fn make_list_auto() -> Vec<String> {
+        // Read shell_cmds.txt
+        let shell_cmds = match std::fs::read_to_string(crate::APP_NAME.to_string() + FILE_NAME) {
+            Ok(content) => content,
+            Err(_) => {
+                crate::log!("shell_cmds.txt not found, using default shell commands");
+                return make_list(DEFAULT);
+            }
+        };
+        make_list(&shell_cmds)
+    }
+
+    pub fn make_list(shell_str: &str) -> Vec<String> {
+        let shell_cmd_name =
+            cmd_data::get_cmd(&cmd_data::make_cmd_list(), &cmd_data::CmdName::ShellQuick);
+        let mut list = Vec::new();
+        for line in shell_str.lines() {
+            // Ignore comments
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            // Trim whitespace
+            let line = line.trim();
+            list.push(format!("{} {}", shell_cmd_name, line));
+        }
+        list
+    }
+}
+
+// Named directories from bookmarks.txt
+mod bookmarks {
+    use std::fs;
+    use std::path::PathBuf;
+
+    const FILE_NAME: &str = "bookmarks.txt";
+    const HEADER: &str = r#"#
+# Bookmarks
+# name           path
+#
+"#;
+
+    pub type BookmarkList = Vec<(String, PathBuf)>;
+
+    pub fn get_path() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or(std::env::current_dir().unwrap())
+            .join(crate::APP_NAME)
+            .join(FILE_NAME)
+    }
+
+    pub fn load() -> BookmarkList {
+        match fs::read_to_string(get_path()) {
+            Ok(content) => make_list(&content),
+            Err(_) => BookmarkList::new(),
+        }
+    }
+
+    fn make_list(bookmarks_str: &str) -> BookmarkList {
+        let mut list = BookmarkList::new();
+        for line in bookmarks_str.lines() {
+            // Ignore comments
+            let line = line.trim();
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+            // The path is everything after the name so it may contain spaces
+            let Some((name, path)) = line.split_once(char::is_whitespace) else {
+                crate::log!("Invalid line in {}: {}", FILE_NAME, line);
+                continue;
+            };
+            let path = path.trim();
+            let path = match path.strip_prefix('~') {
+                Some(rest) => dirs::home_dir()
+                    .unwrap_or_default()
+                    .join(rest.trim_start_matches('/')),
+                None => PathBuf::from(path),
+            };
+            list.push((name.to_string(), path));
+        }
+        list
+    }
+
+    pub fn save(list: &BookmarkList) -> std::io::Result<()> {
+        let path = get_path();
+        fs::create_dir_all(path.parent().unwrap())?;
+        let mut out = HEADER.to_string();
+        for (name, target) in list.iter() {
+            out += &format!("{:<16} {}\n", name, target.to_string_lossy());
+        }
+        fs::write(path, out)
+    }
+
+    pub fn find<'a>(list: &'a BookmarkList, name: &str) -> Option<&'a PathBuf> {
+        list.iter()
+            .find(|(bm_name, _)| bm_name == name)
+            .map(|(_, target)| target)
+    }
+}
+
+// File and directory templates for new-from-template
+mod templates {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    const DIR_NAME: &str = "templates";
+
+    pub fn get_path() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or(std::env::current_dir().unwrap())
+            .join(crate::APP_NAME)
+            .join(DIR_NAME)
+    }
+
+    // Names of the available templates
+    pub fn list() -> Vec<String> {
+        let mut names = match fs::read_dir(get_path()) {
+            Ok(rd) => rd
+                .flatten()
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .collect::<Vec<String>>(),
+            Err(_) => Vec::new(),
+        };
+        names.sort();
+        names
+    }
+
+    fn fill(text: &str, name: &str) -> String {
+        text.replace("{name}", name).replace(
+            "{date}",
+            &chrono::Local::now().format("%Y-%m-%d").to_string(),
+        )
+    }
+
+    // Copy recursively, filling in names and text contents
+    fn copy_filled(src: &Path, dst: &Path, name: &str) -> std::io::Result<()> {
+        if src.is_dir() {
+            fs::create_dir_all(dst)?;
+            for entry in fs::read_dir(src)?.flatten() {
+                let file_name = fill(&entry.file_name().to_string_lossy(), name);
+                copy_filled(&entry.path(), &dst.join(file_name), name)?;
+            }
+            return Ok(());
+        }
+        // Binary files are copied as they are
+        match String::from_utf8(fs::read(src)?) {
+            Ok(text) => fs::write(dst, fill(&text, name))?,
+            Err(e) => fs::write(dst, e.into_bytes())?,
+        }
+        fs::set_permissions(dst, fs::metadata(src)?.permissions())
+    }
+
+    // Create dest from a template, {name} becomes the stem of dest
+    pub fn instantiate(template: &str, dest: &Path) -> Result<(), String> {
+        let src = get_path().join(template);
+        if template.contains('/') || template == ".." || !src.exists() {
+            return Err(format!(
+                "No template named '{}' in {} (available: {})",
+                template,
+                get_path().to_string_lossy(),
+                list().join(", ")
+            ));
+        }
+        if dest.symlink_metadata().is_ok() {
+            return Err(format!("{} already exists", dest.to_string_lossy()));
+        }
+        let name = dest
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        copy_filled(&src, dest, &name).map_err(|e| e.to_string())
+    }
+}
+
+// Shared async filesystem helpers
+// Long running helpers report to an aq::Reporter
+mod fs_ops {
+    use crate::aq;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::{Path, PathBuf};
+    use std::pin::Pin;
+    use tokio::fs;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Recursive async fns need to be boxed
+    type IoFuture<'a, T> = Pin<Box<dyn Future<Output = tokio::io::Result<T>> + Send + 'a>>;
+
+    // Copy a single file in chunks so progress can be reported
+    async fn copy_file(src: &Path, dst: &Path, progress: &aq::Reporter) -> tokio::io::Result<()> {
+        progress.start_file(src);
+        let mut reader = fs::File::open(src).await?;
+        let mut writer = fs::File::create(dst).await?;
+        let mut buf = vec![0u8; 1024 * 1024];
+        loop {
+            progress.checkpoint().await?;
+            let n = reader.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            writer.write_all(&buf[..n]).await?;
+            progress.add_bytes(n as u64);
+        }
+        writer.flush().await?;
+        fs::set_permissions(dst, reader.metadata().await?.permissions()).await?;
+        progress.finish_file();
+        Ok(())
+    }
+
+    // How faithful a copy should be
+    #[derive(Clone, Copy, Default)]
+    pub struct CopyOpts {
+        pub follow_symlinks: bool, // Copy what links point to instead of the links
+        pub preserve_owner: bool,  // Usually needs root
+    }
+    impl CopyOpts {
+        pub fn from_cfg(cfg: &crate::cfg::Config) -> Self {
+            CopyOpts {
+                follow_symlinks: cfg.copy_follow_symlinks,
+                preserve_owner: cfg.copy_preserve_owner,
+            }
+        }
+    }
+
+    // (dev, ino) of the directories above the one being walked
+    type Ancestors = [(u64, u64)];
+
+    // Get metadata, following symlinks only if asked to
+    async fn stat(
+        path: &Path,
+        follow: bool,
+        ancestors: &Ancestors,
+    ) -> tokio::io::Result<std::fs::Metadata> {
+        let meta = fs::symlink_metadata(path).await?;
+        if !follow || !meta.is_symlink() {
+            return Ok(meta);
+        }
+        let target = fs::metadata(path).await?;
+        // A link to a directory already being walked would recurse forever,
+        // however many links the loop goes through
+        if target.is_dir() && ancestors.contains(&(target.dev(), target.ino())) {
+            return Err(tokio::io::Error::other(format!(
+                "Symlink loop: {}",
+                path.to_string_lossy()
+            )));
+        }
+        Ok(target)
+    }
+
+    fn with_dir(ancestors: &Ancestors, meta: &std::fs::Metadata) -> Vec<(u64, u64)> {
+        let mut inner = ancestors.to_vec();
+        inner.push((meta.dev(), meta.ino()));
+        inner
+    }
+
+    // Copy a file, symlink or directory tree
+    // Symlinks are recreated unless followed, permissions and timestamps are kept
+    // Fifos, sockets and devices can't be copied, they are added to skipped instead
+    fn copy_tree<'a>(
+        src: &'a Path,
+        dst: &'a Path,
+        opts: CopyOpts,
+        progress: &'a aq::Reporter,
+        ancestors: &'a Ancestors,
+        skipped: &'a mut Vec<PathBuf>,
+    ) -> IoFuture<'a, ()> {
+        Box::pin(async move {
+            let meta = stat(src, opts.follow_symlinks, ancestors).await?;
+            let file_type = meta.file_type();
+            let atime = filetime::FileTime::from_last_access_time(&meta);
+            let mtime = filetime::FileTime::from_last_modification_time(&meta);
+            if file_type.is_symlink() {
+                fs::symlink(fs::read_link(src).await?, dst).await?;
+                if opts.preserve_owner {
+                    std::os::unix::fs::lchown(dst, Some(meta.uid()), Some(meta.gid()))?;
+                }
+                progress.finish_file();
+                return filetime::set_symlink_file_times(dst, atime, mtime);
+            }
+            if file_type.is_dir() {
+                fs::create_dir_all(dst).await?;
+                let inner = with_dir(ancestors, &meta);
+                let mut dir = fs::read_dir(src).await?;
+                while let Some(entry) = dir.next_entry().await? {
+                    let (src, dst) = (entry.path(), dst.join(entry.file_name()));
+                    copy_tree(&src, &dst, opts, progress, &inner, skipped).await?;
+                }
+            } else if file_type.is_file() {
+                copy_file(src, dst, progress).await?;
+            } else {
+                skipped.push(src.to_path_buf());
+                progress.finish_file();
+                return Ok(());
+            }
+            if opts.preserve_owner {
+                std::os::unix::fs::lchown(dst, Some(meta.uid()), Some(meta.gid()))?;
+            }
+            // Times last, writing into a directory changes its mtime
+            fs::set_permissions(dst, meta.permissions()).await?;
+            filetime::set_file_times(dst, atime, mtime)
+        })
+    }
+
+    // Copy a file or a directory tree
+    // Returns the special files which were skipped
+    pub async fn copy_any(
+        src: &Path,
+        dst: &Path,
+        opts: CopyOpts,
+        progress: &aq::Reporter,
+    ) -> tokio::io::Result<Vec<PathBuf>> {
+        let sum = summarize(src, opts.follow_symlinks, &[]).await?;
+        progress.add_total(sum.files, sum.bytes);
+        let fresh = fs::symlink_metadata(dst).await.is_err();
+        let mut skipped = Vec::new();
+        let res = copy_tree(src, dst, opts, progress, &[], &mut skipped).await;
+        // Don't leave a partial copy behind, e.g. when cancelled
+        if res.is_err() && fresh {
+            let _ = remove_any(dst, &aq::Reporter::none()).await;
+        }
+        res.map(|_| skipped)
+    }
+
+    // Lines listing the special files a copy skipped, for the task output
+    pub fn skipped_note(skipped: &[PathBuf]) -> String {
+        if skipped.is_empty() {
+            return String::new();
+        }
+        format!(
+            "\nSkipped fifos, sockets and devices:{}",
+            path_lines(skipped)
+        )
+    }
+
+    fn path_lines(paths: &[PathBuf]) -> String {
+        paths
+            .iter()
+            .map(|path| format!("\n  {}", path.to_string_lossy()))
+            .collect()
+    }
+
+    // Remove a file, symlink or directory tree
+    pub fn remove_any<'a>(path: &'a Path, progress: &'a aq::Reporter) -> IoFuture<'a, ()> {
+        Box::pin(async move {
+            let meta = fs::symlink_metadata(path).await?;
+            if meta.is_dir() {
+                let mut dir = fs::read_dir(path).await?;
+                while let Some(entry) = dir.next_entry().await? {
+                    remove_any(&entry.path(), progress).await?;
+                }
+                fs::remove_dir(path).await
+            } else {
+                progress.checkpoint().await?;
+                progress.start_file(path);
+                fs::remove_file(path).await?;
+                // Same sizes add_total counted
+                if meta.is_file() {
+                    progress.add_bytes(meta.len());
+                }
+                progress.finish_file();
+                Ok(())
+            }
+        })
+    }
+
+    // What a tree contains
+    #[derive(PartialEq, Default)]
+    struct Summary {
+        entries: u64,
+        files: u64, // Everything that is not a directory
+        bytes: u64,
+    }
+
+    fn summarize<'a>(
+        path: &'a Path,
+        follow: bool,
+        ancestors: &'a Ancestors,
+    ) -> IoFuture<'a, Summary> {
+        Box::pin(async move {
+            let meta = stat(path, follow, ancestors).await?;
+            let mut sum = Summary {
+                entries: 1,
+                ..Default::default()
+            };
+            if meta.is_dir() {
+                let inner = with_dir(ancestors, &meta);
+                let mut dir = fs::read_dir(path).await?;
+                while let Some(entry) = dir.next_entry().await? {
+                    let sub = summarize(&entry.path(), follow, &inner).await?;
+                    sum.entries += sub.entries;
+                    sum.files += sub.files;
+                    sum.bytes += sub.bytes;
+                }
+            } else {
+                sum.files = 1;
+                if meta.is_file() {
+                    sum.bytes = meta.len();
+                }
+            }
+            Ok(sum)
+        })
+    }
+
+    // Add the size of a tree to the progress totals
+    pub async fn add_total(path: &Path, progress: &aq::Reporter) -> tokio::io::Result<()> {
+        let sum = summarize(path, false, &[]).await?;
+        progress.add_total(sum.files, sum.bytes);
+        Ok(())
+    }
+
+    // Move a file or directory, falling back to copy + delete across filesystems
+    // Returns true if the fallback was used
+    pub async fn move_any(
+        src: &Path,
+        dst: &Path,
+        opts: CopyOpts,
+        progress: &aq::Reporter,
+    ) -> tokio::io::Result<bool> {
+        match fs::rename(src, dst).await {
+            Ok(_) => return Ok(false),
+            Err(e) if e.kind() != tokio::io::ErrorKind::CrossesDevices => return Err(e),
+            Err(_) => {}
+        }
+        let src_sum = summarize(src, opts.follow_symlinks, &[]).await?;
+        progress.add_total(src_sum.files, src_sum.bytes);
+        // Only clean up a destination this call created
+        let fresh = fs::symlink_metadata(dst).await.is_err();
+        let mut skipped = Vec::new();
+        let mut res = copy_tree(src, dst, opts, progress, &[], &mut skipped).await;
+        // Deleting the source would lose the special files
+        if res.is_ok() && !skipped.is_empty() {
+            res = Err(tokio::io::Error::other(format!(
+                "Fifos, sockets and devices can't be moved across filesystems:{}",
+                path_lines(&skipped)
+            )));
+        }
+        if let Err(e) = res {
+            if fresh {
+                let _ = remove_any(dst, &aq::Reporter::none()).await;
+            }
+            return Err(e);
+        }
+        // Only remove the source once the copy is known to be complete
+        let dst_sum = summarize(dst, false, &[]).await?;
+        if src_sum != dst_sum {
+            if fresh {
+                let _ = remove_any(dst, &aq::Reporter::none()).await;
+            }
+            return Err(tokio::io::Error::other(format!(
+                "Copy verification failed ({} entries / {} bytes, expected {} / {})",
+                dst_sum.entries, dst_sum.bytes, src_sum.entries, src_sum.bytes
+            )));
+        }
+        remove_any(src, &aq::Reporter::none()).await?;
+        Ok(true)
+    }
+}
+
+// Native archive extraction and creation
+// These are blocking, run them with tokio::task::spawn_blocking
+mod archive {
+    use crate::aq;
+    use std::fs::{self, File};
+    use std::io::{self, Read, Write};
+    use std::path::{Component, Path, PathBuf};
+
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub enum Format {
+        Zip,
+        Tar,
+        TarGz,
+        TarXz,
+        TarZst,
+    }
+    // Known extensions, longest first
+    const EXTENSIONS: [(&str, Format); 8] = [
+        (".tar.gz", Format::TarGz),
+        (".tar.xz", Format::TarXz),
+        (".tar.zst", Format::TarZst),
+        (".tgz", Format::TarGz),
+        (".txz", Format::TarXz),
+        (".tzst", Format::TarZst),
+        (".tar", Format::Tar),
+        (".zip", Format::Zip),
+    ];
+    pub const SUPPORTED: &str = "zip, tar, tar.gz, tar.xz, tar.zst";
+
+    impl Format {
+        pub fn from_path(path: &Path) -> Option<Format> {
+            let name = path.file_name()?.to_string_lossy().to_lowercase();
+            EXTENSIONS
+                .iter()
+                .find(|(ext, _)| name.ends_with(ext))
+                .map(|(_, format)| *format)
+        }
+    }
+
+    // File name without the archive extension
+    pub fn stem(path: &Path) -> String {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let lower = name.to_lowercase();
+        match EXTENSIONS.iter().find(|(ext, _)| lower.ends_with(ext)) {
+            Some((ext, _)) if name.len() > ext.len() => name[..name.len() - ext.len()].to_string(),
+            _ => name,
+        }
+    }
+
+    // Only plain relative paths may be written, anything else could escape
+    pub fn safe_path(path: &Path) -> Option<PathBuf> {
+        let mut out = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(part) => out.push(part),
+                Component::CurDir => {}
+                _ => return None,
+            }
+        }
+        if out.as_os_str().is_empty() {
+            return None;
+        }
+        Some(out)
+    }
+
+    // Reports bytes read from the archive file
+    struct Counting<'a, R> {
+        inner: R,
+        progress: &'a aq::Reporter,
+    }
+    impl<R: Read> Read for Counting<'_, R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.progress.add_bytes(n as u64);
+            Ok(n)
+        }
+    }
+    impl<R: io::Seek> io::Seek for Counting<'_, R> {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    fn unsafe_entry(name: &str) -> io::Error {
+        io::Error::other(format!("Refusing to extract unsafe path '{}'", name))
+    }
+
+    // Extract into dest, which must not exist yet
+    // Returns the number of entries extracted
+    pub fn extract(archive: &Path, dest: &Path, progress: &aq::Reporter) -> io::Result<u64> {
+        unpack(archive, dest, None, progress)
+    }
+
+    // Extract a single member (file or directory) of an archive to dest
+    // The whole path is unpacked next to dest first so links are checked like a full extract
+    pub fn extract_member(
+        archive: &Path,
+        inner: &Path,
+        dest: &Path,
+        progress: &aq::Reporter,
+    ) -> io::Result<u64> {
+        // Next to dest so the rename stays on one filesystem, unique per task
+        let parent = dest.parent().unwrap_or(Path::new("."));
+        let staging = crate::util::private_dir(parent, "extract")?;
+        let res = unpack(archive, &staging.join("out"), Some(inner), progress)
+            .and_then(|count| fs::rename(staging.join("out").join(inner), dest).map(|_| count));
+        let _ = fs::remove_dir_all(&staging);
+        res
+    }
+
+    fn unpack(
+        archive: &Path,
+        dest: &Path,
+        only: Option<&Path>,
+        progress: &aq::Reporter,
+    ) -> io::Result<u64> {
+        let format = Format::from_path(archive).ok_or(io::Error::other(format!(
+            "Unsupported archive, expected one of: {}",
+            SUPPORTED
+        )))?;
+        let file = File::open(archive)?;
+        progress.add_total(0, file.metadata()?.len());
+        let reader = Counting {
+            inner: io::BufReader::new(file),
+            progress,
+        };
+        fs::create_dir(dest)?;
+        let res = match format {
+            Format::Zip => extract_zip(reader, dest, only, progress),
+            _ => decoder(reader, format).and_then(|r| extract_tar(r, dest, only, progress)),
+        };
+        if res.is_err() {
+            let _ = fs::remove_dir_all(dest);
+        }
+        res
+    }
+
+    // Tar stream of any of the tar formats
+    fn decoder<'a, R: Read + 'a>(reader: R, format: Format) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match format {
+            Format::TarGz => Box::new(flate2::read::GzDecoder::new(reader)),
+            Format::TarXz => Box::new(xz2::read::XzDecoder::new(reader)),
+            Format::TarZst => Box::new(zstd::Decoder::new(reader)?),
+            _ => Box::new(reader),
+        })
+    }
+
+    // Whether an entry is wanted when extracting only part of an archive
+    fn wanted(path: &Path, only: Option<&Path>) -> bool {
+        only.is_none_or(|only| safe_path(path).is_some_and(|path| path.starts_with(only)))
+    }
+
+    fn extract_tar<R: Read>(
+        reader: R,
+        dest: &Path,
+        only: Option<&Path>,
+        progress: &aq::Reporter,
+    ) -> io::Result<u64> {
+        let mut archive = tar::Archive::new(reader);
+        archive.set_preserve_permissions(true);
+        archive.set_preserve_mtime(true);
+        let mut count = 0;
+        for entry in archive.entries()? {
+            progress.checkpoint_blocking()?;
+            let mut entry = entry?;
+            let path = entry.path()?.to_path_buf();
+            if safe_path(&path).is_none() {
+                return Err(unsafe_entry(&path.to_string_lossy()));
+            }
+            if !wanted(&path, only) {
+                continue;
+            }
+            progress.start_file(&path);
+            // unpack_in also refuses to write through symlinks leading outside dest
+            if !entry.unpack_in(dest)? {
+                return Err(unsafe_entry(&path.to_string_lossy()));
+            }
+            progress.finish_file();
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // Every symlink below dest was made by this extraction, like tar's unpack_in
+    // refuse to write anything through one
+    fn check_no_links(dest: &Path, rel: &Path) -> io::Result<()> {
+        let mut path = dest.to_path_buf();
+        for part in rel.iter() {
+            path.push(part);
+            match fs::symlink_metadata(&path) {
+                Ok(meta) if meta.file_type().is_symlink() => {
+                    return Err(unsafe_entry(&rel.to_string_lossy()));
+                }
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn extract_zip<R: Read + io::Seek>(
+        reader: R,
+        dest: &Path,
+        only: Option<&Path>,
+        progress: &aq::Reporter,
+    ) -> io::Result<u64> {
+        let mut archive = zip::ZipArchive::new(reader)?;
+        let mut count = 0;
+        let mut links = Vec::new();
+        for i in 0..archive.len() {
+            progress.checkpoint_blocking()?;
+            let mut file = archive.by_index(i)?;
+            let rel = match file.enclosed_name().as_deref().and_then(safe_path) {
+                Some(rel) => rel,
+                None => return Err(unsafe_entry(file.name())),
+            };
+            if !wanted(&rel, only) {
+                continue;
+            }
+            progress.start_file(&rel);
+            check_no_links(dest, &rel)?;
+            let out = dest.join(&rel);
+            if let Some(parent) = out.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if file.is_dir() {
+                fs::create_dir_all(&out)?;
+            } else if file.is_symlink() {
+                let mut target = String::new();
+                file.read_to_string(&mut target)?;
+                // Links may only point somewhere inside the archive
+                let resolved = rel.parent().unwrap_or(Path::new("")).join(&target);
+                let inside = resolved.components().try_fold(0i32, |depth, c| match c {
+                    Component::Normal(_) => Some(depth + 1),
+                    Component::CurDir => Some(depth),
+                    Component::ParentDir if depth > 0 => Some(depth - 1),
+                    _ => None,
+                });
+                if inside.is_none() {
+                    return Err(unsafe_entry(file.name()));
+                }
+                std::os::unix::fs::symlink(&target, &out)?;
+                links.push(out);
+            } else {
+                io::copy(&mut file, &mut File::create(&out)?)?;
+                if let Some(mode) = file.unix_mode() {
+                    use std::os::unix::fs::PermissionsExt;
+                    fs::set_permissions(&out, fs::Permissions::from_mode(mode & 0o7777))?;
+                }
+            }
+            progress.finish_file();
+            count += 1;
+        }
+        // Links through other links can still lead outside, like a -> b/.. and c -> a/..
+        let root = fs::canonicalize(dest)?;
+        for link in links.iter() {
+            if fs::canonicalize(link).is_ok_and(|real| !real.starts_with(&root)) {
+                let rel = link.strip_prefix(dest).unwrap_or(link);
+                return Err(unsafe_entry(&rel.to_string_lossy()));
+            }
+        }
+        Ok(count)
+    }
+
+    // A member of an archive
+    #[derive(Clone, Debug)]
+    pub struct Entry {
+        pub path: PathBuf,
+        pub is_dir: bool,
+        pub size: u64, // Uncompressed
+    }
+
+    // Split a path into the archive file it passes through and the path inside it
+    // The inner path is empty for the archive itself
+    pub fn split(path: &Path) -> Option<(PathBuf, PathBuf)> {
+        let archive = path
+            .ancestors()
+            .find(|p| Format::from_path(p).is_some() && p.is_file())?;
+        let inner = path.strip_prefix(archive).ok()?.to_path_buf();
+        Some((archive.to_path_buf(), inner))
+    }
+
+    // Every member of an archive sorted by path
+    // Directories which only exist as part of a member path are included
+    pub fn entries(archive: &Path) -> io::Result<Vec<Entry>> {
+        let format = Format::from_path(archive).ok_or(io::Error::other("Unsupported archive"))?;
+        let reader = io::BufReader::new(File::open(archive)?);
+        let mut found = Vec::new();
+        if format == Format::Zip {
+            let mut zip = zip::ZipArchive::new(reader)?;
+            for i in 0..zip.len() {
+                let file = zip.by_index_raw(i)?;
+                if let Some(path) = file.enclosed_name().as_deref().and_then(safe_path) {
+                    found.push(Entry {
+                        path,
+                        is_dir: file.is_dir(),
+                        size: file.size(),
+                    });
+                }
+            }
+        } else {
+            let mut tar = tar::Archive::new(decoder(reader, format)?);
+            for entry in tar.entries()? {
+                let entry = entry?;
+                if let Some(path) = safe_path(&entry.path()?) {
+                    found.push(Entry {
+                        path,
+                        is_dir: entry.header().entry_type().is_dir(),
+                        size: entry.header().size().unwrap_or(0),
+                    });
+                }
+            }
+        }
+        let mut map = std::collections::BTreeMap::new();
+        for entry in found {
+            for parent in entry.path.ancestors().skip(1) {
+                if parent.as_os_str().is_empty() {
+                    break;
+                }
+                map.entry(parent.to_path_buf()).or_insert(Entry {
+                    path: parent.to_path_buf(),
+                    is_dir: true,
+                    size: 0,
+                });
+            }
+            map.insert(entry.path.clone(), entry);
+        }
+        Ok(map.into_values().collect())
+    }
+
+    // Read up to limit bytes of a file inside an archive
+    pub fn read_member(archive: &Path, inner: &Path, limit: u64) -> io::Result<Vec<u8>> {
+        let format = Format::from_path(archive).ok_or(io::Error::other("Unsupported archive"))?;
+        let reader = io::BufReader::new(File::open(archive)?);
+        let mut buf = Vec::new();
+        if format == Format::Zip {
+            let mut zip = zip::ZipArchive::new(reader)?;
+            for i in 0..zip.len() {
+                let file = zip.by_index(i)?;
+                let path = file.enclosed_name().as_deref().and_then(safe_path);
+                if !file.is_dir() && path.as_deref() == Some(inner) {
+                    file.take(limit).read_to_end(&mut buf)?;
+                    return Ok(buf);
+                }
+            }
+        } else {
+            let mut tar = tar::Archive::new(decoder(reader, format)?);
+            for entry in tar.entries()? {
+                let entry = entry?;
+                let path = safe_path(&entry.path()?);
+                if !entry.header().entry_type().is_dir() && path.as_deref() == Some(inner) {
+                    entry.take(limit).read_to_end(&mut buf)?;
+                    return Ok(buf);
+                }
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not in the archive", inner.to_string_lossy()),
+        ))
+    }
+
+    // Walk paths in the order they are added to an archive
+    // Yields (path on disk, name in archive), names are relative to each path's parent
+    fn walk(path: &Path, name: PathBuf, out: &mut Vec<(PathBuf, PathBuf)>) -> io::Result<()> {
+        let meta = fs::symlink_metadata(path)?;
+        out.push((path.to_path_buf(), name.clone()));
+        if meta.is_dir() {
+            let mut entries = fs::read_dir(path)?.flatten().collect::<Vec<_>>();
+            entries.sort_by_key(|entry| entry.file_name());
+            for entry in entries {
+                walk(&entry.path(), name.join(entry.file_name()), out)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Create dest from paths, the format comes from the extension of dest
+    // Returns the number of entries written
+    pub fn compress(paths: &[PathBuf], dest: &Path, progress: &aq::Reporter) -> io::Result<u64> {
+        let format = Format::from_path(dest).ok_or(io::Error::other(format!(
+            "Unknown archive extension, expected one of: {}",
+            SUPPORTED
+        )))?;
+        let mut entries = Vec::new();
+        for path in paths.iter() {
+            let name = path.file_name().ok_or(io::Error::other("Invalid path"))?;
+            walk(path, PathBuf::from(name), &mut entries)?;
+        }
+        let file = File::create_new(dest)?;
+        let res = match format {
+            Format::Zip => compress_zip(file, &entries, progress),
+            Format::Tar => compress_tar(file, &entries, progress).map(|_| ()),
+            Format::TarGz => compress_tar(
+                flate2::write::GzEncoder::new(file, flate2::Compression::default()),
+                &entries,
+                progress,
+            )
+            .and_then(|encoder| encoder.finish().map(|_| ())),
+            Format::TarXz => compress_tar(xz2::write::XzEncoder::new(file, 6), &entries, progress)
+                .and_then(|encoder| encoder.finish().map(|_| ())),
+            Format::TarZst => compress_tar(zstd::Encoder::new(file, 0)?, &entries, progress)
+                .and_then(|encoder| encoder.finish().map(|_| ())),
+        };
+        if res.is_err() {
+            let _ = fs::remove_file(dest);
+        }
+        res.map(|_| entries.len() as u64)
+    }
+
+    // Returns the writer so compressors can be finished
+    fn compress_tar<W: Write>(
+        writer: W,
+        entries: &[(PathBuf, PathBuf)],
+        progress: &aq::Reporter,
+    ) -> io::Result<W> {
+        let mut builder = tar::Builder::new(writer);
+        builder.follow_symlinks(false);
+        for (path, name) in entries.iter() {
+            progress.checkpoint_blocking()?;
+            progress.start_file(path);
+            let meta = fs::symlink_metadata(path)?;
+            if meta.is_dir() {
+                builder.append_dir(name, path)?;
+            } else {
+                builder.append_path_with_name(path, name)?;
+                if meta.is_file() {
+                    progress.add_bytes(meta.len());
+                }
+                progress.finish_file();
+            }
+        }
+        builder.into_inner()
+    }
+
+    fn compress_zip(
+        file: File,
+        entries: &[(PathBuf, PathBuf)],
+        progress: &aq::Reporter,
+    ) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        let mut zip = zip::ZipWriter::new(file);
+        for (path, name) in entries.iter() {
+            progress.checkpoint_blocking()?;
+            progress.start_file(path);
+            let meta = fs::symlink_metadata(path)?;
+            let options = zip::write::SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated)
+                .unix_permissions(meta.permissions().mode() & 0o7777);
+            let name = name.to_string_lossy();
+            if meta.is_dir() {
+                zip.add_directory(name, options)?;
+                continue;
+            }
+            if meta.is_symlink() {
+                let target = fs::read_link(path)?;
+                zip.add_symlink(name, target.to_string_lossy(), options)?;
+            } else {
+                zip.start_file(name, options)?;
+                let copied = io::copy(&mut File::open(path)?, &mut zip)?;
+                progress.add_bytes(copied);
+            }
+            progress.finish_file();
+        }
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+// Content search, finds lines containing a query in files under a directory
+mod grep {
+    use crate::node_info::{NodeInfo, NodeType};
+    use crate::{BINARY_SNIFF_BYTES, util};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tokio::sync::mpsc::UnboundedSender;
+
+    // Typing this first in the search box searches file contents
+    pub const PREFIX: char = '/';
+    // Larger files are skipped
+    const MAX_FILE_BYTES: u64 = 8 * 1024 * 1024;
+    const MAX_MATCHES: usize = 10_000;
+    const SNIPPET_CHARS: usize = 120;
+
+    // Get the path and line number back from a "path:line: snippet" match
+    pub fn parse(name: &str) -> Option<(PathBuf, usize)> {
+        for (i, _) in name.match_indices(':') {
+            let rest = &name[i + 1..];
+            let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+            if digits > 0 && rest[digits..].starts_with(": ") {
+                return Some((PathBuf::from(&name[..i]), rest[..digits].parse().ok()?));
+            }
+        }
+        None
+    }
+
+    // Send each match to tx as it is found, paths are relative to root
+    // Case is ignored unless the query has uppercase letters
+    // Returns the number of matches, stops early once the receiver is gone
+    pub fn search(
+        root: &Path,
+        query: &str,
+        recursive: bool,
+        show_hidden: bool,
+        tx: &UnboundedSender<NodeInfo>,
+    ) -> usize {
+        let ignore_case = !query.chars().any(|c| c.is_uppercase());
+        let needle = if ignore_case {
+            query.to_lowercase()
+        } else {
+            query.to_string()
+        };
+        let mut count = 0;
+        walk(
+            root,
+            root,
+            &needle,
+            ignore_case,
+            recursive,
+            show_hidden,
+            tx,
+            &mut count,
+        );
+        count
+    }
+
+    // Returns false once the search should stop
+    #[allow(clippy::too_many_arguments)]
+    fn walk(
+        root: &Path,
+        dir: &Path,
+        needle: &str,
+        ignore_case: bool,
+        recursive: bool,
+        show_hidden: bool,
+        tx: &UnboundedSender<NodeInfo>,
+        count: &mut usize,
+    ) -> bool {
+        let mut entries = match fs::read_dir(dir) {
+            Ok(read_dir) => read_dir.flatten().collect::<Vec<_>>(),
+            Err(_) => return true,
+        };
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            if tx.is_closed() {
+                return false;
+            }
+            if !show_hidden && entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(_) => continue,
+            };
+            if file_type.is_dir() {
+                if recursive
+                    && !walk(
+                        root,
+                        &path,
+                        needle,
+                        ignore_case,
+                        recursive,
+                        show_hidden,
+                        tx,
+                        count,
+                    )
+                {
+                    return false;
+                }
+                continue;
+            }
+            if !file_type.is_file() || entry.metadata().map_or(true, |m| m.len() > MAX_FILE_BYTES) {
+                continue;
+            }
+            let bytes = match fs::read(&path) {
+                Ok(bytes) => bytes,
+                Err(_) => continue,
+            };
+            if util::is_binary(&bytes[..bytes.len().min(BINARY_SNIFF_BYTES)]) {
+                continue;
+            }
+            let rel = path.strip_prefix(root).unwrap_or(&path).to_string_lossy();
+            for (i, line) in String::from_utf8_lossy(&bytes).lines().enumerate() {
+                let found = if ignore_case {
+                    line.to_lowercase().contains(needle)
+                } else {
+                    line.contains(needle)
+                };
+                if !found {
+                    continue;
+                }
+                let snippet = line.trim().replace('\t', " ");
+                let snippet = snippet.chars().take(SNIPPET_CHARS).collect::<String>();
+                let node = NodeInfo {
+                    name: format!("{}:{}: {}", rel, i + 1, snippet),
+                    node_type: NodeType::Match,
+                    stat: None,
+                };
+                if tx.send(node).is_err() {
+                    return false;
+                }
+                *count += 1;
+                if *count >= MAX_MATCHES {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn path_and_line() {
+            let parsed = parse("src/main.rs:12: fn main() {");
+            assert_eq!(parsed, Some((PathBuf::from("src/main.rs"), 12)));
+        }
+
+        #[test]
+        fn colons_in_path_and_snippet() {
+            assert_eq!(parse("a:b:3: x"), Some((PathBuf::from("a:b"), 3)));
+            assert_eq!(parse("x:7: y: 9: z"), Some((PathBuf::from("x"), 7)));
+        }
+
+        #[test]
+        fn not_a_match() {
+            assert_eq!(parse("file:12:no space"), None);
+            assert_eq!(parse("file:: x"), None);
+            assert_eq!(parse("plain name"), None);
+        }
+    }
+}
+
+// Visited directories ranked by how often and how recently they were visited
+// Works like the zoxide database
+mod frecency {
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::SystemTime;
+
+    const FILE_NAME: &str = "frecency.txt";
+    // Once all ranks add up to more than this they are scaled down
+    const MAX_TOTAL: f64 = 10_000.0;
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct Entry {
+        pub path: PathBuf,
+        pub rank: f64,
+        pub last: u64, // Last visit in seconds since the epoch
+    }
+    impl Entry {
+        // Rank weighted by how long ago the directory was visited
+        pub fn score(&self, now: u64) -> f64 {
+            let age = now.saturating_sub(self.last);
+            let weight = match age {
+                0..3600 => 4.0,
+                3600..86400 => 2.0,
+                86400..604800 => 0.5,
+                _ => 0.25,
+            };
+            self.rank * weight
+        }
+    }
+
+    pub struct Db {
+        entries: Vec<Entry>,
+    }
+    impl Db {
+        pub fn get_path() -> PathBuf {
+            dirs::data_dir()
+                .unwrap_or(std::env::current_dir().unwrap())
+                .join(crate::APP_NAME)
+                .join(FILE_NAME)
+        }
+
+        // One "rank<TAB>last<TAB>path" line per directory
+        pub fn load() -> Self {
+            let mut db = Db {
+                entries: Vec::new(),
+            };
+            let content = match std::fs::read_to_string(Db::get_path()) {
+                Ok(content) => content,
+                Err(_) => return db,
+            };
+            for line in content.lines() {
+                let split = line.splitn(3, '\t').collect::<Vec<&str>>();
+                if split.len() != 3 {
+                    continue;
+                }
+                if let (Ok(rank), Ok(last)) = (split[0].parse::<f64>(), split[1].parse::<u64>()) {
+                    db.entries.push(Entry {
+                        path: PathBuf::from(split[2]),
+                        rank,
+                        last,
+                    });
+                }
+            }
+            db
+        }
+
+        // Written off the UI thread, through a temp file renamed over the db so
+        // it is never left half written. Saves may finish out of order, so one
+        // never replaces the file with an older state than it already holds
+        fn save(&self) {
+            static QUEUED: AtomicU64 = AtomicU64::new(0);
+            static WRITTEN: Mutex<u64> = Mutex::new(0);
+            let generation = QUEUED.fetch_add(1, Ordering::Relaxed) + 1;
+            let mut text = String::new();
+            for entry in self.entries.iter() {
+                text += &format!(
+                    "{}\t{}\t{}\n",
+                    entry.rank,
+                    entry.last,
+                    entry.path.to_string_lossy()
+                );
+            }
+            tokio::task::spawn_blocking(move || {
+                let mut written = WRITTEN.lock().unwrap_or_else(|e| e.into_inner());
+                if *written > generation {
+                    return;
+                }
+                let path = Db::get_path();
+                let tmp = path.with_extension(format!("txt.{}.tmp", std::process::id()));
+                let res = std::fs::create_dir_all(path.parent().unwrap())
+                    .and_then(|_| std::fs::write(&tmp, text))
+                    .and_then(|_| std::fs::rename(&tmp, &path));
+                match res {
+                    Ok(_) => *written = generation,
+                    Err(e) => {
+                        let _ = std::fs::remove_file(&tmp);
+                        crate::log!("Failed to write {}: {}", FILE_NAME, e);
+                    }
+                }
+            });
+        }
+
+        // Scale ranks down so old favourites fade out, tiny ranks are dropped
+        fn age(&mut self) {
+            let total: f64 = self.entries.iter().map(|entry| entry.rank).sum();
+            if total > MAX_TOTAL {
+                let factor = 0.9 * MAX_TOTAL / total;
+                for entry in self.entries.iter_mut() {
+                    entry.rank *= factor;
+                }
+                self.entries.retain(|entry| entry.rank >= 1.0);
+            }
+        }
+
+        pub fn visit(&mut self, path: &Path) {
+            let now = now();
+            match self.entries.iter_mut().find(|entry| entry.path == path) {
+                Some(entry) => {
+                    entry.rank += 1.0;
+                    entry.last = now;
+                }
+                None => self.entries.push(Entry {
+                    path: path.to_path_buf(),
+                    rank: 1.0,
+                    last: now,
+                }),
+            }
+            self.age();
+            self.save();
+        }
+
+        // Best first
+        pub fn sorted(&self) -> Vec<Entry> {
+            let now = now();
+            let mut entries = self.entries.clone();
+            entries.sort_by(|a, b| b.score(now).total_cmp(&a.score(now)));
+            entries
+        }
+
+        // Remove directories which no longer exist
+        // Returns how many were removed
+        pub fn prune(&mut self) -> usize {
+            let before = self.entries.len();
+            self.entries.retain(|entry| entry.path.is_dir());
+            self.save();
+            before - self.entries.len()
+        }
+
+        // Add ranks from another database
+        // Returns how many directories were new
+        pub fn import(&mut self, found: Vec<(PathBuf, f64)>) -> usize {
+            let now = now();
+            let mut added = 0;
+            for (path, rank) in found {
+                match self.entries.iter_mut().find(|entry| entry.path == path) {
+                    Some(entry) => entry.rank += rank,
+                    None => {
+                        self.entries.push(Entry {
+                            path,
+                            rank,
+                            last: now,
+                        });
+                        added += 1;
+                    }
+                }
+            }
+            self.age();
+            self.save();
+            added
+        }
+    }
+
+    // Parse "score path" lines like "  12.5 /home/me/code"
+    fn parse_scores(text: &str, sep: char) -> Vec<(PathBuf, f64)> {
+        text.lines()
+            .filter_map(|line| {
+                let (score, path) = line.trim_start().split_once(sep)?;
+                let score = score.trim().parse::<f64>().ok()?;
+                Some((PathBuf::from(path.trim_start()), score))
+            })
+            .collect()
+    }
+
+    pub fn read_zoxide() -> Result<Vec<(PathBuf, f64)>, String> {
+        let output = Command::new("zoxide")
+            .args(["query", "--list", "--score"])
+            .output()
+            .map_err(|e| format!("Failed to run zoxide: {}", e))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        Ok(parse_scores(&String::from_utf8_lossy(&output.stdout), ' '))
+    }
+
+    // autojump keeps "weight<TAB>path" lines in its data directory
+    pub fn read_autojump() -> Result<Vec<(PathBuf, f64)>, String> {
+        let path = dirs::data_dir()
+            .ok_or("No data directory")?
+            .join("autojump")
+            .join("autojump.txt");
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.to_string_lossy(), e))?;
+        Ok(parse_scores(&text, '\t'))
+    }
+}
+
+// A small blocking question shown in a popup
+// The user answers by pressing one of the option keys
+mod prompt {
+    use crate::App;
+
+    pub struct Prompt {
+        pub title: String,
+        pub text: String,
+        pub options: Vec<(char, &'static str)>,
+        // Called with the pressed key, or None if the prompt was cancelled
+        // Uppercase variants of the option keys are passed through as-is
+        pub on_answer: fn(&mut App, Option<char>),
+    }
+    impl Prompt {
+        pub fn accepts(&self, c: char) -> bool {
+            self.options
+                .iter()
+                .any(|(key, _)| *key == c.to_ascii_lowercase())
+        }
+    }
+}
+
+// Renaming items in place, shared by the rename commands
+mod rename {
+    use crate::{App, aq, journal, prompt};
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+
+    // Check (from, to) pairs, dropping the ones which do not change anything
+    // Targets may be other sources, that is a swap or cycle and is handled by apply
+    pub fn validate(pairs: Vec<(PathBuf, PathBuf)>) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+        let plan = pairs
+            .into_iter()
+            .filter(|(from, to)| from != to)
+            .collect::<Vec<(PathBuf, PathBuf)>>();
+        let sources = plan.iter().map(|(from, _)| from).collect::<HashSet<_>>();
+        let mut targets = HashSet::new();
+        let mut errors = Vec::new();
+        for (from, to) in plan.iter() {
+            let to_str = to.to_string_lossy();
+            if to.file_name().is_none() {
+                errors.push(format!("Invalid name for {}", from.to_string_lossy()));
+            } else if !targets.insert(to) {
+                errors.push(format!("{} is the target of more than one rename", to_str));
+            } else if to.symlink_metadata().is_ok() && !sources.contains(to) {
+                errors.push(format!("{} already exists", to_str));
+            } else if !to.parent().is_some_and(|parent| parent.is_dir()) {
+                errors.push(format!("Parent directory of {} does not exist", to_str));
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+        Ok(plan)
+    }
+
+    // Dry run shown before renaming
+    pub fn diff(plan: &[(PathBuf, PathBuf)]) -> String {
+        let mut out = String::new();
+        for (from, to) in plan.iter() {
+            out += &format!("- {}\n+ {}\n", from.to_string_lossy(), to.to_string_lossy());
+        }
+        out
+    }
+
+    // Everything is moved to a temporary name first so swaps and cycles
+    // never overwrite each other, failures roll back what was done
+    pub fn apply(plan: &[(PathBuf, PathBuf)]) -> Result<(), String> {
+        let temps = plan
+            .iter()
+            .enumerate()
+            .map(|(i, (from, _))| {
+                from.with_file_name(format!(
+                    ".{}-rename-{}-{}",
+                    crate::APP_NAME,
+                    std::process::id(),
+                    i
+                ))
+            })
+            .collect::<Vec<PathBuf>>();
+        let rollback = |moved: &[(&PathBuf, &PathBuf)]| {
+            for (from, to) in moved.iter().rev() {
+                let _ = std::fs::rename(to, from);
+            }
+        };
+        let mut stage = Vec::new();
+        for ((from, _), temp) in plan.iter().zip(temps.iter()) {
+            if let Err(e) = std::fs::rename(from, temp) {
+                rollback(&stage);
+                return Err(format!(
+                    "Failed to rename {}: {}",
+                    from.to_string_lossy(),
+                    e
+                ));
+            }
+            stage.push((from, temp));
+        }
+        let mut done = Vec::new();
+        for ((_, to), temp) in plan.iter().zip(temps.iter()) {
+            if let Err(e) = std::fs::rename(temp, to) {
+                rollback(&done);
+                rollback(&stage);
+                return Err(format!(
+                    "Failed to rename to {}: {}",
+                    to.to_string_lossy(),
+                    e
+                ));
+            }
+            done.push((temp, to));
+        }
+        Ok(())
+    }
+
+    // Apply a validated plan and record it for undo
+    // Undo and redo replay the plan through apply as a whole
+    pub fn commit(app: &mut App, plan: Vec<(PathBuf, PathBuf)>) {
+        if let Err(e) = apply(&plan) {
+            app.set_output("Rename", &format!("Nothing renamed.\n{}", e));
+            return;
+        }
+        for (from, to) in plan.iter() {
+            for sel in app.multi_selection.iter_mut() {
+                if sel == from {
+                    *sel = to.clone();
+                }
+            }
+        }
+        app.set_output("Rename", &format!("Renamed {} item(s).", plan.len()));
+        // Stamping walks the renamed trees, so the ops are made in a task
+        let batch = app.journal.begin();
+        app.async_queue.add_task(aq::Kind::FsOperation, async move {
+            let ops = plan
+                .iter()
+                .map(|(from, to)| journal::Op::new(batch, journal::OpKind::Rename, from, to))
+                .collect::<Vec<journal::Op>>();
+            aq::ResData::as_ops(0, format!("Renamed {} item(s)", ops.len()), ops)
+        });
+        app.update_listing();
+        app.update_results();
+    }
+
+    // Show the dry run in the output window and ask before renaming
+    pub fn confirm(app: &mut App, title: &str, plan: Vec<(PathBuf, PathBuf)>) {
+        if plan.is_empty() {
+            app.set_output(title, "Nothing to rename.");
+            return;
+        }
+        app.set_output(title, &diff(&plan));
+        app.prompt = Some(prompt::Prompt {
+            title: title.to_string(),
+            text: format!(
+                "Rename {} item(s)? The changes are listed in the output window.",
+                plan.len()
+            ),
+            options: vec![('y', "rename")],
+            on_answer,
+        });
+        app.rename_plan = plan;
+    }
+
+    // Apply a case transform like "upper" to a placeholder value
+    fn transform(value: &str, modifier: &str) -> Result<String, String> {
+        match modifier {
+            "" => Ok(value.to_string()),
+            "upper" => Ok(value.to_uppercase()),
+            "lower" => Ok(value.to_lowercase()),
+            "title" => {
+                let mut out = String::new();
+                let mut start = true;
+                for c in value.chars() {
+                    if start {
+                        out.extend(c.to_uppercase());
+                    } else {
+                        out.extend(c.to_lowercase());
+                    }
+                    start = !c.is_alphanumeric();
+                }
+                Ok(out)
+            }
+            _ => Err(format!("Unknown transform '{}'", modifier)),
+        }
+    }
+
+    // Expand {n}, {n:3}, {ext}, {stem}, {1} and {name} style placeholders
+    // Any placeholder but {n:3} takes a :upper, :lower or :title transform
+    // $1, ${1} and $$ are left to the regex, {{ is a literal {
+    fn expand(
+        template: &str,
+        caps: &regex::Captures,
+        name: &str,
+        n: usize,
+    ) -> Result<String, String> {
+        let path = std::path::Path::new(name);
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_string());
+        let ext = path.extension().map(|s| s.to_string_lossy().to_string());
+        let mut out = String::new();
+        let mut rest = template;
+        while let Some(at) = rest.find(['{', '$']) {
+            out += &rest[..at];
+            rest = &rest[at..];
+            if let Some(after) = rest.strip_prefix("{{") {
+                out += "{";
+                rest = after;
+                continue;
+            }
+            if rest.starts_with('$') {
+                // Copy the whole reference so its braces are not taken as a placeholder
+                let len = if rest.starts_with("${") {
+                    match rest.find('}') {
+                        Some(close) => close + 1,
+                        None => return Err("Unclosed '${' in pattern".to_string()),
+                    }
+                } else if rest.starts_with("$$") {
+                    2
+                } else {
+                    1
+                };
+                out += &rest[..len];
+                rest = &rest[len..];
+                continue;
+            }
+            let close = match rest.find('}') {
+                Some(close) => close,
+                None => return Err("Unclosed '{' in pattern".to_string()),
+            };
+            let inner = &rest[1..close];
+            let (key, modifier) = inner.split_once(':').unwrap_or((inner, ""));
+            let value = match key {
+                "n" => match modifier.parse::<usize>() {
+                    Ok(width) => format!("{:0width$}", n, width = width),
+                    Err(_) if modifier.is_empty() => n.to_string(),
+                    Err(_) => return Err(format!("Invalid counter width '{}'", modifier)),
+                },
+                "stem" => transform(&stem.clone().unwrap_or_default(), modifier)?,
+                "ext" => transform(&ext.clone().unwrap_or_default(), modifier)?,
+                _ => match key.parse::<usize>() {
+                    Ok(i) => transform(caps.get(i).map_or("", |m| m.as_str()), modifier)?,
+                    Err(_) => match caps.name(key) {
+                        Some(m) => transform(m.as_str(), modifier)?,
+                        None => return Err(format!("Unknown placeholder '{{{}}}'", inner)),
+                    },
+                },
+            };
+            // Keep $ in values from being read as capture references
+            out += &value.replace('$', "$$");
+            rest = &rest[close + 1..];
+        }
+        out += rest;
+        let mut expanded = String::new();
+        caps.expand(&out, &mut expanded);
+        Ok(expanded)
+    }
+
+    // Rename the first match of `from` in each file name to `to`
+    // Names which do not match are left alone, {n} counts matches from 1
+    pub fn pattern_pairs(
+        paths: &[PathBuf],
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+        let re = regex::Regex::new(from).map_err(|e| e.to_string())?;
+        let mut pairs = Vec::new();
+        for path in paths.iter() {
+            let name = match path.file_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => continue,
+            };
+            let caps = match re.captures(&name) {
+                Some(caps) => caps,
+                None => continue,
+            };
+            let whole = caps.get(0).unwrap();
+            let replaced = expand(to, &caps, &name, pairs.len() + 1)?;
+            let new_name = format!(
+                "{}{}{}",
+                &name[..whole.start()],
+                replaced,
+                &name[whole.end()..]
+            );
+            if new_name.is_empty() || new_name.contains('/') {
+                return Err(format!("Invalid new name for {}: '{}'", name, new_name));
+            }
+            pairs.push((path.clone(), path.with_file_name(new_name)));
+        }
+        Ok(pairs)
+    }
+
+    fn on_answer(app: &mut App, answer: Option<char>) {
+        let plan = std::mem::take(&mut app.rename_plan);
+        match answer {
+            Some('y' | 'Y') => commit(app, plan),
+            _ => app.set_output("Rename", "Rename cancelled."),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn run(from: &str, to: &str, name: &str, n: usize) -> Result<String, String> {
+            let re = regex::Regex::new(from).unwrap();
+            let caps = re.captures(name).unwrap();
+            expand(to, &caps, name, n)
+        }
+
+        #[test]
+        fn counter() {
+            assert_eq!(run(".*", "{n}", "a", 7).unwrap(), "7");
+            assert_eq!(run(".*", "{n:3}", "a", 7).unwrap(), "007");
+        }
+
+        #[test]
+        fn name_parts_and_transforms() {
+            assert_eq!(
+                run(".*", "{stem:upper}.{ext}", "photo.jpg", 1).unwrap(),
+                "PHOTO.jpg"
+            );
+            assert_eq!(run(".*", "{ext:title}", "a.JPG", 1).unwrap(), "Jpg");
+            assert_eq!(
+                run(r"(\w+ \w+)", "{1:title}", "hello WORLD", 1).unwrap(),
+                "Hello World"
+            );
+            assert_eq!(run(r"(?<y>\d{4})", "{y}", "2024", 1).unwrap(), "2024");
+        }
+
+        #[test]
+        fn dollar_in_value_is_kept() {
+            // A $ in a value is not read as a reference
+            assert_eq!(run(".*", "{stem}", "a$1", 1).unwrap(), "a$1");
+        }
+
+        #[test]
+        fn errors() {
+            assert!(run(".*", "{bogus}", "a", 1).is_err());
+            assert!(run(".*", "{n", "a", 1).is_err());
+            assert!(run(".*", "{n:x}", "a", 1).is_err());
+            assert!(run(".*", "{stem:shout}", "a", 1).is_err());
+        }
+
+        #[test]
+        fn regex_references_are_left_alone() {
+            assert_eq!(run("(foo)", "${1}x", "foo", 1).unwrap(), "foox");
+            assert_eq!(run("(foo)", "$1-{n}", "foo", 2).unwrap(), "foo-2");
+            assert_eq!(run("(foo)", "$$", "foo", 1).unwrap(), "$");
+        }
+
+        #[test]
+        fn escapes() {
+            assert_eq!(run(".*", "{{n}", "a", 1).unwrap(), "{n}");
+            assert!(run(".*", "${1", "a", 1).is_err());
+        }
+    }
+}
+
+// Handling copy/move destinations which already exist
+mod conflict {
+    use crate::{App, aq, archive, fs_ops, journal, prompt, trash};
+    use std::path::{Path, PathBuf};
+
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub enum Policy {
+        Ask,
+        Overwrite,
+        Skip,
+        Rename,
+        Newer, // Overwrite only if the source is newer
+    }
+    impl Policy {
+        pub fn from_str(s: &str) -> Option<Policy> {
+            match s.to_lowercase().as_str() {
+                "ask" => Some(Policy::Ask),
+                "overwrite" => Some(Policy::Overwrite),
+                "skip" => Some(Policy::Skip),
+                "rename" => Some(Policy::Rename),
+                "newer" => Some(Policy::Newer),
+                _ => None,
+            }
+        }
+        fn from_key(c: char) -> Option<Policy> {
+            match c.to_ascii_lowercase() {
+                'o' => Some(Policy::Overwrite),
+                's' => Some(Policy::Skip),
+                'r' => Some(Policy::Rename),
+                'n' => Some(Policy::Newer),
+                _ => None,
+            }
+        }
+    }
+
+    // A copy or move waiting to be queued
+    #[derive(Clone)]
+    pub struct Pending {
+        pub kind: journal::OpKind,
+        pub batch: usize,
+        pub src: PathBuf,
+        pub dest: PathBuf,
+    }
+
+    // Find a free "name (n).ext" next to the given path
+    pub fn unique_name(path: &Path) -> PathBuf {
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let ext = path
+            .extension()
+            .map(|e| format!(".{}", e.to_string_lossy()))
+            .unwrap_or_default();
+        let mut n = 1;
+        loop {
+            let candidate = path.with_file_name(format!("{} ({}){}", stem, n, ext));
+            if candidate.symlink_metadata().is_err() {
+                return candidate;
+            }
+            n += 1;
+        }
+    }
+
+    fn is_newer(src: &Path, dest: &Path) -> bool {
+        let modified = |p: &Path| p.symlink_metadata().and_then(|m| m.modified()).ok();
+        match (modified(src), modified(dest)) {
+            (Some(src), Some(dest)) => src > dest,
+            _ => false,
+        }
+    }
+
+    // Queue a copy or move of the multi selection into the cwd
+    // Destinations that already exist are resolved by the conflict policy
+    pub fn transfer_sel(app: &mut App, kind: journal::OpKind) {
+        if archive::split(&app.cwd).is_some() {
+            app.set_output(
+                "Error",
+                "Archives can not be modified, leave the archive first.",
+            );
+            return;
+        }
+        let from_archive =
+            |p: &PathBuf| archive::split(p).is_some_and(|(_, inner)| !inner.as_os_str().is_empty());
+        if kind == journal::OpKind::Move && app.multi_selection.iter().any(from_archive) {
+            app.set_output(
+                "Error",
+                "Files can not be moved out of an archive, copy them instead.",
+            );
+            return;
+        }
+        let batch = app.journal.begin();
+        app.conflict_all = None;
+        for path in app.multi_selection.clone() {
+            let file_name = match path.file_name() {
+                Some(name) => name.to_owned(),
+                None => continue,
+            };
+            let item = Pending {
+                kind: kind.clone(),
+                batch,
+                dest: app.cwd.join(&file_name),
+                src: path,
+            };
+            if item.dest.symlink_metadata().is_ok() {
+                app.conflicts.push(item);
+            } else {
+                queue(app, item, false);
+            }
+        }
+        app.multi_selection.clear();
+        resolve(app);
+    }
+
+    // Queue the actual operation
+    // When replacing, the existing destination is moved to the trash first
+    fn queue(app: &mut App, item: Pending, replace: bool) {
+        let label = format!(
+            "{} {} -> {}",
+            item.kind.as_str(),
+            item.src.to_string_lossy(),
+            item.dest.to_string_lossy()
         );
-        map.insert(
-            CmdName::ConfigClear,
-            CmdData {
-                fname: "Config Clear",
-                description: "Clear (delete) the configuration files",
-                cmd: "config-clear",
-                vis_hidden: false,
-                params: vec![],
-                on_sel: false,
-                op: cmd::config_clear,
-            },
+        let opts = fs_ops::CopyOpts::from_cfg(&app.cfg);
+        let retry_item = item.clone();
+        let id = app
+            .async_queue
+            .add_task_progress(aq::Kind::FsOperation, label, move |progress| {
+                let item = item.clone();
+                async move {
+                    let (src, dest) = (&item.src, &item.dest);
+                    let mut ops = Vec::new();
+                    if replace {
+                        match trash::put(dest, opts, &aq::Reporter::none()).await {
+                            Ok(name) => ops.push(journal::Op::new(
+                                item.batch,
+                                journal::OpKind::Trash,
+                                dest,
+                                &PathBuf::from(name),
+                            )),
+                            Err(e) => {
+                                return aq::ResData::as_str(
+                                    1,
+                                    format!("Failed to replace {}: {}", dest.to_string_lossy(), e),
+                                );
+                            }
+                        }
+                    }
+                    let member =
+                        archive::split(src).filter(|(_, inner)| !inner.as_os_str().is_empty());
+                    let (res, verb) = match (&item.kind, member) {
+                        // Pull the member out of its archive
+                        (journal::OpKind::Copy, Some((archive_path, inner))) => {
+                            let dest = dest.clone();
+                            (
+                                tokio::task::spawn_blocking(move || {
+                                    archive::extract_member(&archive_path, &inner, &dest, &progress)
+                                })
+                                .await
+                                .unwrap_or_else(|e| Err(std::io::Error::other(e)))
+                                .map(|_| String::new()),
+                                "copy",
+                            )
+                        }
+                        (journal::OpKind::Copy, None) => (
+                            fs_ops::copy_any(src, dest, opts, &progress)
+                                .await
+                                .map(|skipped| fs_ops::skipped_note(&skipped)),
+                            "copy",
+                        ),
+                        _ => (
+                            fs_ops::move_any(src, dest, opts, &progress)
+                                .await
+                                .map(|fallback| match fallback {
+                                    true => " (copied across filesystems)".to_string(),
+                                    false => String::new(),
+                                }),
+                            "move",
+                        ),
+                    };
+                    match res {
+                        Ok(note) => {
+                            ops.push(journal::Op::new(item.batch, item.kind.clone(), src, dest));
+                            aq::ResData::as_ops(
+                                0,
+                                format!(
+                                    "{} {} to {}{}",
+                                    if verb == "copy" { "Copied" } else { "Moved" },
+                                    src.to_string_lossy(),
+                                    dest.to_string_lossy(),
+                                    note
+                                ),
+                                ops,
+                            )
+                        }
+                        // Keep any trash op so the replaced item can still be restored with undo
+                        Err(e) => aq::ResData::as_ops(
+                            1,
+                            format!("Failed to {} {}: {}", verb, src.to_string_lossy(), e),
+                            ops,
+                        ),
+                    }
+                }
+            });
+        app.async_queue.set_retry(
+            id,
+            aq::Retry::Rebuild(Box::new(move |app| retry(app, retry_item))),
         );
-        map.insert(
-            CmdName::ConfigGoto,
-            CmdData {
-                fname: "Config Goto",
-                description: "Go to the configuration directory",
-                cmd: "config-goto",
-                vis_hidden: false,
-                params: vec![],
-                on_sel: false,
-                op: cmd::config_goto,
-            },
+    }
+
+    // Queue a failed or cancelled transfer again in a new batch
+    // The destination may have been taken since, so it is checked again
+    fn retry(app: &mut App, item: Pending) {
+        if app.conflicts.is_empty() {
+            app.conflict_all = None;
+        }
+        let item = Pending {
+            batch: app.journal.begin(),
+            ..item
+        };
+        if item.dest.symlink_metadata().is_ok() {
+            app.conflicts.push(item);
+        } else {
+            queue(app, item, false);
+        }
+        resolve(app);
+    }
+
+    // Skipped items are reported like any other finished task
+    fn skip(app: &mut App, item: Pending) {
+        app.async_queue.add_task(aq::Kind::FsOperation, async move {
+            aq::ResData::as_str(
+                0,
+                format!(
+                    "Skipped {} ({} exists)",
+                    item.src.to_string_lossy(),
+                    item.dest.to_string_lossy()
+                ),
+            )
+        });
+    }
+
+    fn apply(app: &mut App, mut item: Pending, policy: Policy) {
+        // Copying or moving something onto itself can only ever make a renamed copy
+        let same = item.src == item.dest;
+        match policy {
+            Policy::Overwrite if !same => queue(app, item, true),
+            Policy::Newer if !same && is_newer(&item.src, &item.dest) => queue(app, item, true),
+            Policy::Rename if !(same && item.kind == journal::OpKind::Move) => {
+                item.dest = unique_name(&item.dest);
+                queue(app, item, false);
+            }
+            _ => skip(app, item),
+        }
+    }
+
+    // Work through pending conflicts until one needs the user to decide
+    pub fn resolve(app: &mut App) {
+        while !app.conflicts.is_empty() {
+            let policy = app.conflict_all.unwrap_or(app.cfg.conflict_policy);
+            if policy == Policy::Ask {
+                ask(app);
+                return;
+            }
+            let item = app.conflicts.remove(0);
+            apply(app, item, policy);
+        }
+    }
+
+    fn ask(app: &mut App) {
+        let item = &app.conflicts[0];
+        let describe = |p: &Path| match p.symlink_metadata() {
+            Ok(meta) => {
+                let modified = meta
+                    .modified()
+                    .map(|t| {
+                        chrono::DateTime::<chrono::Local>::from(t)
+                            .format("%Y-%m-%d %H:%M:%S")
+                            .to_string()
+                    })
+                    .unwrap_or("?".to_string());
+                let kind = if meta.is_dir() { "directory" } else { "file" };
+                format!("{}, {} bytes, modified {}", kind, meta.len(), modified)
+            }
+            Err(_) => "missing".to_string(),
+        };
+        let text = format!(
+            "The destination already exists:\n  {}\n  ({})\n\nSource:\n  {}\n  ({})\n\n{} conflict(s) left. Hold shift to apply the choice to all.",
+            item.dest.to_string_lossy(),
+            describe(&item.dest),
+            item.src.to_string_lossy(),
+            describe(&item.src),
+            app.conflicts.len()
         );
+        app.prompt = Some(prompt::Prompt {
+            title: format!(
+                "{} conflict",
+                if item.kind == journal::OpKind::Copy {
+                    "Copy"
+                } else {
+                    "Move"
+                }
+            ),
+            text,
+            options: vec![
+                ('o', "overwrite"),
+                ('s', "skip"),
+                ('r', "rename"),
+                ('n', "overwrite if newer"),
+            ],
+            on_answer,
+        });
+    }
+
+    fn on_answer(app: &mut App, answer: Option<char>) {
+        let c = match answer {
+            Some(c) => c,
+            None => {
+                // Cancelled, skip everything that is left
+                for item in std::mem::take(&mut app.conflicts) {
+                    skip(app, item);
+                }
+                return;
+            }
+        };
+        let policy = match Policy::from_key(c) {
+            Some(policy) => policy,
+            None => return,
+        };
+        if c.is_uppercase() {
+            app.conflict_all = Some(policy);
+        } else {
+            let item = app.conflicts.remove(0);
+            apply(app, item, policy);
+        }
+        resolve(app);
+    }
+}
+
+// Persistent journal of filesystem operations, used for undo/redo
+mod journal {
+    use crate::{aq, fs_ops, trash};
+    use std::collections::HashSet;
+    use std::ffi::OsString;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Path, PathBuf};
+    use std::time::SystemTime;
+
+    const FILE_NAME: &str = "journal.txt";
+    // How many batches are kept on the undo stack
+    const MAX_BATCHES: usize = 100;
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum OpKind {
+        Copy,
+        Move,
+        MkDir,
+        Trash,
+        Rename, // One pair of a rename plan, see steps
+    }
+    impl OpKind {
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                OpKind::Copy => "copy",
+                OpKind::Move => "move",
+                OpKind::MkDir => "mkdir",
+                OpKind::Trash => "trash",
+                OpKind::Rename => "rename",
+            }
+        }
+        fn from_str(s: &str) -> Option<OpKind> {
+            match s {
+                "copy" => Some(OpKind::Copy),
+                "move" => Some(OpKind::Move),
+                "mkdir" => Some(OpKind::MkDir),
+                "trash" => Some(OpKind::Trash),
+                "rename" => Some(OpKind::Rename),
+                _ => None,
+            }
+        }
+    }
+
+    // A single recorded operation
+    // All ops created by one command share a batch id and are undone together
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Op {
+        pub batch: usize,
+        pub kind: OpKind,
+        pub src: PathBuf, // Source path (mkdir: the new dir, trash: the original path)
+        pub dst: PathBuf, // Destination path (mkdir: unused, trash: name inside the trash)
+        pub stamp: u64,   // Fingerprint of the result tree, used to detect later changes
+    }
+    impl Op {
+        pub fn new(batch: usize, kind: OpKind, src: &Path, dst: &Path) -> Self {
+            let result = match kind {
+                OpKind::Copy | OpKind::Move | OpKind::Rename => dst,
+                OpKind::MkDir => src,
+                OpKind::Trash => Path::new(""),
+            };
+            Op {
+                batch,
+                kind,
+                src: src.to_path_buf(),
+                dst: dst.to_path_buf(),
+                stamp: stamp(result),
+            }
+        }
+
+        pub fn describe(&self) -> String {
+            match self.kind {
+                OpKind::MkDir => format!("mkdir {}", self.src.to_string_lossy()),
+                OpKind::Trash => format!("trash {}", self.src.to_string_lossy()),
+                _ => format!(
+                    "{} {} -> {}",
+                    self.kind.as_str(),
+                    self.src.to_string_lossy(),
+                    self.dst.to_string_lossy()
+                ),
+            }
+        }
+
+        // Make sure the filesystem still looks like it did right after this op ran
+        // sim holds what the later ops of the batch change once they are undone
+        // The source of a rename is checked with the rest of its step in check_undo_step
+        fn check_undo(&self, sim: &Sim) -> Result<(), String> {
+            let changed = |what: &str| Err(format!("{}: {}", self.describe(), what));
+            match self.kind {
+                OpKind::Copy | OpKind::Move | OpKind::Rename => {
+                    if !sim.exists(&self.dst) {
+                        return changed("destination no longer exists");
+                    }
+                    if !sim.placed(&self.dst) && stamp(&self.dst) != self.stamp {
+                        return changed("destination or something inside it was modified since");
+                    }
+                    if self.kind == OpKind::Move && sim.exists(&self.src) {
+                        return changed("something new exists at the source path");
+                    }
+                }
+                OpKind::MkDir => {
+                    if !sim.is_empty_dir(&self.src) {
+                        return changed("directory is missing or no longer empty");
+                    }
+                }
+                OpKind::Trash => {
+                    let name = self.dst.to_string_lossy();
+                    if !trash::list().iter().any(|item| item.name == name) {
+                        return changed("item is no longer in the trash");
+                    }
+                    if sim.exists(&self.src) {
+                        return changed("something new exists at the original path");
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        // What undoing the op changes on the filesystem
+        fn sim_undo(&self, sim: &mut Sim) {
+            match self.kind {
+                OpKind::Copy => sim.free(&self.dst),
+                OpKind::Move => {
+                    sim.free(&self.dst);
+                    sim.place(&self.src);
+                }
+                OpKind::MkDir => sim.free(&self.src),
+                OpKind::Trash => sim.place(&self.src),
+                OpKind::Rename => sim.free(&self.dst),
+            }
+        }
+
+        // Make sure the op can be applied again
+        // sim holds what the earlier ops of the batch change once they are redone
+        // The destination of a rename is checked with the rest of its step in check_redo_step
+        fn check_redo(&self, sim: &Sim) -> Result<(), String> {
+            let blocked = |what: &str| Err(format!("{}: {}", self.describe(), what));
+            match self.kind {
+                OpKind::Copy | OpKind::Move => {
+                    if !sim.exists(&self.src) {
+                        return blocked("source no longer exists");
+                    }
+                    if sim.exists(&self.dst) {
+                        return blocked("destination already exists");
+                    }
+                }
+                OpKind::MkDir => {
+                    if sim.exists(&self.src) {
+                        return blocked("path already exists");
+                    }
+                }
+                OpKind::Trash => {
+                    if !sim.exists(&self.src) {
+                        return blocked("original path no longer exists");
+                    }
+                }
+                OpKind::Rename => {
+                    if !sim.exists(&self.src) {
+                        return blocked("source no longer exists");
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        // What redoing the op changes on the filesystem
+        fn sim_redo(&self, sim: &mut Sim) {
+            match self.kind {
+                OpKind::Copy => sim.place(&self.dst),
+                OpKind::Move => {
+                    sim.free(&self.src);
+                    sim.place(&self.dst);
+                }
+                OpKind::MkDir => sim.place(&self.src),
+                OpKind::Trash => sim.free(&self.src),
+                OpKind::Rename => sim.free(&self.src),
+            }
+        }
+
+        // Reverse the op
+        async fn undo(
+            &self,
+            opts: fs_ops::CopyOpts,
+            progress: &aq::Reporter,
+        ) -> Result<(), String> {
+            let res = match self.kind {
+                OpKind::Copy => fs_ops::remove_any(&self.dst, progress).await,
+                OpKind::Move => fs_ops::move_any(&self.dst, &self.src, opts, progress)
+                    .await
+                    .map(|_| ()),
+                OpKind::MkDir => tokio::fs::remove_dir(&self.src).await,
+                OpKind::Rename => tokio::fs::rename(&self.dst, &self.src).await,
+                OpKind::Trash => {
+                    return trash::restore(&self.dst.to_string_lossy(), opts, progress)
+                        .await
+                        .map(|_| ());
+                }
+            };
+            res.map_err(|e| format!("{}: {}", self.describe(), e))
+        }
+
+        // Apply the op again, returning the new op to record and a note for the output
+        async fn redo(
+            &self,
+            batch: usize,
+            opts: fs_ops::CopyOpts,
+            progress: &aq::Reporter,
+        ) -> Result<(Op, String), String> {
+            let mut note = String::new();
+            let res = match self.kind {
+                OpKind::Copy => fs_ops::copy_any(&self.src, &self.dst, opts, progress)
+                    .await
+                    .map(|skipped| {
+                        note = fs_ops::skipped_note(&skipped);
+                    }),
+                OpKind::Move => fs_ops::move_any(&self.src, &self.dst, opts, progress)
+                    .await
+                    .map(|_| ()),
+                OpKind::MkDir => tokio::fs::create_dir(&self.src).await,
+                OpKind::Rename => tokio::fs::rename(&self.src, &self.dst).await,
+                OpKind::Trash => {
+                    let name = trash::put(&self.src, opts, progress).await?;
+                    let op = Op::new(batch, OpKind::Trash, &self.src, Path::new(&name));
+                    return Ok((op, note));
+                }
+            };
+            res.map_err(|e| format!("{}: {}", self.describe(), e))?;
+            let op = Op::new(batch, self.kind.clone(), &self.src, &self.dst);
+            Ok((op, note))
+        }
+    }
+
+    // The filesystem as it will be after some ops of a batch are replayed
+    // Lets a whole batch be checked before anything is touched
+    #[derive(Default)]
+    struct Sim {
+        freed: Vec<PathBuf>,      // Removed, along with everything under them
+        placed: HashSet<PathBuf>, // Created, their content was checked with the op
+    }
+    impl Sim {
+        fn placed(&self, path: &Path) -> bool {
+            self.placed.contains(path)
+        }
+        fn exists(&self, path: &Path) -> bool {
+            if self.placed(path) {
+                return true;
+            }
+            if self.freed.iter().any(|freed| path.starts_with(freed)) {
+                return false;
+            }
+            path.symlink_metadata().is_ok()
+        }
+        fn is_empty_dir(&self, path: &Path) -> bool {
+            if !self.exists(path) || self.placed(path) {
+                return false;
+            }
+            if self
+                .placed
+                .iter()
+                .any(|placed| placed.parent() == Some(path))
+            {
+                return false;
+            }
+            std::fs::read_dir(path)
+                .map(|rd| rd.flatten().all(|entry| !self.exists(&entry.path())))
+                .unwrap_or(false)
+        }
+        fn free(&mut self, path: &Path) {
+            self.placed.remove(path);
+            self.freed.push(path.to_path_buf());
+        }
+        fn place(&mut self, path: &Path) {
+            self.placed.insert(path.to_path_buf());
+        }
+    }
 
-        map
+    // Fingerprint of every name, size and modified time under path, 0 if unavailable
+    // FNV-1a so the value stays the same across builds
+    pub fn stamp(path: &Path) -> u64 {
+        fn feed(hash: &mut u64, bytes: &[u8]) {
+            for b in bytes {
+                *hash ^= *b as u64;
+                *hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+        fn walk(hash: &mut u64, path: &Path, rel: &Path) {
+            let Ok(meta) = std::fs::symlink_metadata(path) else {
+                return;
+            };
+            let modified = meta
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map(|duration| duration.as_nanos())
+                .unwrap_or(0);
+            feed(hash, rel.as_os_str().as_bytes());
+            feed(hash, &[0]);
+            feed(hash, &meta.len().to_le_bytes());
+            feed(hash, &modified.to_le_bytes());
+            if !meta.is_dir() {
+                return;
+            }
+            let mut names = match std::fs::read_dir(path) {
+                Ok(rd) => rd
+                    .flatten()
+                    .map(|entry| entry.file_name())
+                    .collect::<Vec<_>>(),
+                Err(_) => return,
+            };
+            names.sort();
+            for name in names {
+                walk(hash, &path.join(&name), &rel.join(&name));
+            }
+        }
+        if std::fs::symlink_metadata(path).is_err() {
+            return 0;
+        }
+        let mut hash = 0xcbf29ce484222325;
+        walk(&mut hash, path, Path::new(""));
+        hash
+    }
+
+    // Paths are stored percent encoded so tabs, newlines and non UTF-8 names survive
+    fn escape(path: &Path) -> String {
+        let mut out = String::new();
+        for &b in path.as_os_str().as_bytes() {
+            if b == b'%' || !(0x20..0x7f).contains(&b) {
+                out += &format!("%{:02X}", b);
+            } else {
+                out.push(b as char);
+            }
+        }
+        out
+    }
+
+    fn unescape(s: &str) -> PathBuf {
+        let bytes = s.as_bytes();
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            let hex = bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok());
+            match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                Some(b) if bytes[i] == b'%' => {
+                    out.push(b);
+                    i += 3;
+                }
+                _ => {
+                    out.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+        PathBuf::from(OsString::from_vec(out))
+    }
+
+    pub struct Journal {
+        undo: Vec<Op>,
+        redo: Vec<Op>,
+        next_batch: usize,
+    }
+    impl Journal {
+        pub fn get_path() -> PathBuf {
+            dirs::data_dir()
+                .unwrap_or(std::env::current_dir().unwrap())
+                .join(crate::APP_NAME)
+                .join(FILE_NAME)
+        }
+
+        pub fn load() -> Self {
+            let mut journal = Journal {
+                undo: Vec::new(),
+                redo: Vec::new(),
+                next_batch: 0,
+            };
+            let content = match std::fs::read_to_string(Journal::get_path()) {
+                Ok(content) => content,
+                Err(_) => return journal,
+            };
+            for line in content.lines() {
+                let split = line.split('\t').collect::<Vec<&str>>();
+                if split.len() != 6 {
+                    continue;
+                }
+                let (batch, kind, stamp) = match (
+                    split[1].parse::<usize>(),
+                    OpKind::from_str(split[2]),
+                    split[3].parse::<u64>(),
+                ) {
+                    (Ok(batch), Some(kind), Ok(stamp)) => (batch, kind, stamp),
+                    _ => continue,
+                };
+                let op = Op {
+                    batch,
+                    kind,
+                    stamp,
+                    src: unescape(split[4]),
+                    dst: unescape(split[5]),
+                };
+                journal.next_batch = journal.next_batch.max(batch + 1);
+                match split[0] {
+                    "undo" => journal.undo.push(op),
+                    "redo" => journal.redo.push(op),
+                    _ => {}
+                }
+            }
+            journal
+        }
+
+        fn save(&self) {
+            let mut text = String::new();
+            for (stack, ops) in [("undo", &self.undo), ("redo", &self.redo)] {
+                for op in ops.iter() {
+                    text += &format!(
+                        "{}\t{}\t{}\t{}\t{}\t{}\n",
+                        stack,
+                        op.batch,
+                        op.kind.as_str(),
+                        op.stamp,
+                        escape(&op.src),
+                        escape(&op.dst)
+                    );
+                }
+            }
+            let path = Journal::get_path();
+            let _ = std::fs::create_dir_all(path.parent().unwrap());
+            if let Err(e) = std::fs::write(&path, text) {
+                crate::log!("Failed to write {}: {}", FILE_NAME, e);
+            }
+        }
+
+        // Start a new batch for a fresh command, this invalidates the redo stack
+        pub fn begin(&mut self) -> usize {
+            if !self.redo.is_empty() {
+                self.redo.clear();
+                self.save();
+            }
+            self.begin_redo()
+        }
+
+        // Start a new batch without touching the redo stack
+        fn begin_redo(&mut self) -> usize {
+            let batch = self.next_batch;
+            self.next_batch += 1;
+            batch
+        }
+
+        pub fn record(&mut self, op: Op) {
+            self.undo.push(op);
+            // Drop the oldest batches
+            let mut batches: Vec<usize> = self.undo.iter().map(|op| op.batch).collect();
+            batches.sort();
+            batches.dedup();
+            if batches.len() > MAX_BATCHES {
+                let keep_from = batches[batches.len() - MAX_BATCHES];
+                self.undo.retain(|op| op.batch >= keep_from);
+            }
+            self.save();
+        }
+
+        // The ops of the most recent batch
+        // Ops of commands which ran at the same time can be interleaved so they are picked by id
+        fn last_batch(ops: &[Op]) -> Vec<Op> {
+            match ops.last() {
+                Some(last) => ops
+                    .iter()
+                    .filter(|op| op.batch == last.batch)
+                    .cloned()
+                    .collect(),
+                None => Vec::new(),
+            }
+        }
+
+        // The most recent batch on the undo stack
+        // The batch is reversed with undo_batch and stays on the stack until it finishes
+        pub fn undo(&self) -> Result<Vec<Op>, String> {
+            let batch = Journal::last_batch(&self.undo);
+            if batch.is_empty() {
+                return Err("Nothing to undo.".to_string());
+            }
+            Ok(batch)
+        }
+
+        // The most recent batch on the redo stack
+        // The batch is reapplied with redo_batch under the returned batch id
+        pub fn redo(&mut self) -> Result<(Vec<Op>, usize), String> {
+            let batch = Journal::last_batch(&self.redo);
+            if batch.is_empty() {
+                return Err("Nothing to redo.".to_string());
+            }
+            Ok((batch, self.begin_redo()))
+        }
+
+        // Move the ops an undo or redo task got through to the other stack
+        pub fn finish(&mut self, replayed: Replayed) {
+            match replayed {
+                Replayed::Undo(ops) => {
+                    self.undo.retain(|op| !ops.contains(op));
+                    self.redo.extend(ops);
+                }
+                // The reapplied ops come back through record
+                Replayed::Redo(ops) => self.redo.retain(|op| !ops.contains(op)),
+            }
+            self.save();
+        }
+    }
+
+    // Ops which were undone or redone, in the order they were recorded
+    #[derive(Clone, Debug)]
+    pub enum Replayed {
+        Undo(Vec<Op>),
+        Redo(Vec<Op>),
+    }
+
+    // A batch is replayed in steps, each a single op or all the renames of a rename plan
+    // A plan goes through rename::apply as a whole so swaps and cycles never overwrite
+    // each other and a failure rolls the whole plan back
+    fn steps(batch: &[Op]) -> Vec<&[Op]> {
+        batch
+            .chunk_by(|a, b| a.kind == OpKind::Rename && b.kind == OpKind::Rename)
+            .collect()
+    }
+
+    // Run rename::apply off the async workers
+    async fn apply_renames(plan: Vec<(PathBuf, PathBuf)>) -> Result<(), String> {
+        tokio::task::spawn_blocking(move || crate::rename::apply(&plan))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()))
+    }
+
+    // Check a step against sim, then add what undoing it changes
+    // Renames only need their sources free once every destination of the plan is gone
+    fn check_undo_step(step: &[Op], sim: &mut Sim) -> Result<(), String> {
+        for op in step {
+            op.check_undo(sim)?;
+        }
+        for op in step {
+            op.sim_undo(sim);
+        }
+        let renames = step.iter().filter(|op| op.kind == OpKind::Rename);
+        for op in renames.clone() {
+            if sim.exists(&op.src) {
+                return Err(format!(
+                    "{}: something new exists at the source path",
+                    op.describe()
+                ));
+            }
+        }
+        for op in renames {
+            sim.place(&op.src);
+        }
+        Ok(())
+    }
+
+    async fn undo_step(
+        step: &[Op],
+        opts: fs_ops::CopyOpts,
+        progress: &aq::Reporter,
+    ) -> Result<(), String> {
+        match step {
+            [op] => op.undo(opts, progress).await,
+            _ => {
+                let plan = step
+                    .iter()
+                    .rev()
+                    .map(|op| (op.dst.clone(), op.src.clone()))
+                    .collect();
+                apply_renames(plan).await
+            }
+        }
+    }
+
+    // Reverse a batch taken from Journal::undo, newest op first
+    // The whole batch is checked first, each step against the state the later steps leave
+    // behind, so the trash op of an overwrite finds its path free once the copy is undone
+    // Only the ops which were undone go to the redo stack
+    pub async fn undo_batch(
+        batch: Vec<Op>,
+        opts: fs_ops::CopyOpts, // Moves across filesystems copy with the current settings
+        progress: aq::Reporter,
+    ) -> (u32, String, Replayed) {
+        let mut sim = Sim::default();
+        for step in steps(&batch).into_iter().rev() {
+            if let Err(e) = check_undo_step(step, &mut sim) {
+                let out = format!(
+                    "Refusing to undo, the filesystem changed since the operation ran.\n{}\n",
+                    e
+                );
+                return (1, out, Replayed::Undo(Vec::new()));
+            }
+        }
+        let mut rc = 0;
+        let mut out = String::new();
+        let mut undone = Vec::new();
+        for step in steps(&batch).into_iter().rev() {
+            match undo_step(step, opts, &progress).await {
+                Ok(_) => {
+                    for op in step.iter().rev() {
+                        out += &format!("Undid {}\n", op.describe());
+                        undone.insert(0, op.clone());
+                    }
+                }
+                Err(e) => {
+                    rc = 1;
+                    out += &format!("Failed to undo {}\n", e);
+                    break;
+                }
+            }
+        }
+        (rc, out, Replayed::Undo(undone))
+    }
+
+    // Check a step against sim, then add what redoing it changes
+    // Renames only need their destinations free once every source of the plan is gone
+    fn check_redo_step(step: &[Op], sim: &mut Sim) -> Result<(), String> {
+        for op in step {
+            op.check_redo(sim)?;
+        }
+        for op in step {
+            op.sim_redo(sim);
+        }
+        let renames = step.iter().filter(|op| op.kind == OpKind::Rename);
+        for op in renames.clone() {
+            if sim.exists(&op.dst) {
+                return Err(format!("{}: destination already exists", op.describe()));
+            }
+        }
+        for op in renames {
+            sim.place(&op.dst);
+        }
+        Ok(())
+    }
+
+    // Returns the new ops to record, each with a note for the output
+    async fn redo_step(
+        step: &[Op],
+        batch: usize,
+        opts: fs_ops::CopyOpts,
+        progress: &aq::Reporter,
+    ) -> Result<Vec<(Op, String)>, String> {
+        match step {
+            [op] => op
+                .redo(batch, opts, progress)
+                .await
+                .map(|redone| vec![redone]),
+            _ => {
+                let plan = step
+                    .iter()
+                    .map(|op| (op.src.clone(), op.dst.clone()))
+                    .collect();
+                apply_renames(plan).await?;
+                Ok(step
+                    .iter()
+                    .map(|op| {
+                        (
+                            Op::new(batch, OpKind::Rename, &op.src, &op.dst),
+                            String::new(),
+                        )
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    // Reapply a batch taken from Journal::redo, yielding the ops to record
+    // Like undo_batch the whole batch is checked before anything is redone
+    pub async fn redo_batch(
+        batch: Vec<Op>,
+        new_batch: usize,
+        opts: fs_ops::CopyOpts, // Copies are redone with the current settings
+        progress: aq::Reporter,
+    ) -> (u32, String, Vec<Op>, Replayed) {
+        let mut sim = Sim::default();
+        for step in steps(&batch) {
+            if let Err(e) = check_redo_step(step, &mut sim) {
+                let out = format!("Refusing to redo.\n{}\n", e);
+                return (1, out, Vec::new(), Replayed::Redo(Vec::new()));
+            }
+        }
+        let mut rc = 0;
+        let mut out = String::new();
+        let mut ops = Vec::new();
+        let mut redone = Vec::new();
+        for step in steps(&batch) {
+            match redo_step(step, new_batch, opts, &progress).await {
+                Ok(new_ops) => {
+                    for (op, (new_op, note)) in step.iter().zip(new_ops) {
+                        out += &format!("Redid {}{}\n", op.describe(), note);
+                        ops.push(new_op);
+                        redone.push(op.clone());
+                    }
+                }
+                Err(e) => {
+                    rc = 1;
+                    out += &format!("Failed to redo {}\n", e);
+                    break;
+                }
+            }
+        }
+        (rc, out, ops, Replayed::Redo(redone))
     }
 }
 
-// Color scheme management
-mod cs {
-    use crate::log;
-    use ratatui::style::Color;
-    use std::fs;
+// FreeDesktop trash management
+// https://specifications.freedesktop.org/trash-spec/latest/
+mod trash {
+    use crate::{aq, fs_ops};
+    use std::ffi::OsString;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Path, PathBuf};
+    use tokio::fs;
+
+    pub struct TrashItem {
+        pub name: String,    // Name inside the trash "files" dir
+        pub orig: PathBuf,   // Where the item was deleted from
+        pub deleted: String, // Deletion date as written in the .trashinfo
+    }
 
-    const FILE_NAME: &str = "colors.txt";
-    pub const DEFAULT: &str = r#"
-#
-# Default color
-#
+    pub fn get_path() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or(std::env::current_dir().unwrap())
+            .join("Trash")
+    }
 
-name           default
-search_border  green
-preview_border yellow
-listing_border blue
-file           green
-dir            blue
-command        cyan
-executable     lightred
-shortcut       yellow
-image          lightmagenta
-header         lightblue
-info           yellow
-tip            green
-warning        yellow
-error          red
-ok             green
-hi             white
-dim            gray
-misc           white
-"#;
-    // Color scheme struct
-    #[derive(Clone)]
-    pub struct Colors {
-        pub name: String,
-        pub search_border: Color,
-        pub preview_border: Color,
-        pub listing_border: Color,
-        pub file: Color,
-        pub dir: Color,
-        pub command: Color,
-        pub executable: Color,
-        pub shortcut: Color,
-        pub image: Color,
-        pub info: Color,
-        pub tip: Color,
-        pub warning: Color,
-        pub error: Color,
-        pub ok: Color,
-        pub header: Color,
-        pub hi: Color,
-        pub dim: Color,
-        pub misc: Color,
+    fn files_dir() -> PathBuf {
+        get_path().join("files")
     }
-    impl Colors {
-        pub fn new() -> Self {
-            Self {
-                name: "default".to_string(),
-                search_border: Color::White,
-                preview_border: Color::White,
-                listing_border: Color::White,
-                file: Color::White,
-                dir: Color::White,
-                command: Color::White,
-                executable: Color::White,
-                shortcut: Color::White,
-                image: Color::White,
-                info: Color::White,
-                tip: Color::White,
-                warning: Color::White,
-                error: Color::White,
-                ok: Color::White,
-                header: Color::White,
-                hi: Color::White,
-                dim: Color::White,
-                misc: Color::White,
+
+    fn info_dir() -> PathBuf {
+        get_path().join("info")
+    }
+
+    fn info_path(name: &str) -> PathBuf {
+        info_dir().join(format!("{}.trashinfo", name))
+    }
+
+    // Percent encode a path for the trashinfo "Path" key
+    // Raw bytes are used so names which aren't UTF-8 survive
+    fn encode(path: &Path) -> String {
+        let mut out = String::new();
+        for &b in path.as_os_str().as_bytes() {
+            match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                    out.push(b as char)
+                }
+                _ => out += &format!("%{:02X}", b),
             }
         }
-        pub fn from_str(s: &str) -> Color {
-            match s.to_lowercase().as_str() {
-                "black" => Color::Black,
-                "red" => Color::Red,
-                "green" => Color::Green,
-                "yellow" => Color::Yellow,
-                "blue" => Color::Blue,
-                "magenta" => Color::Magenta,
-                "cyan" => Color::Cyan,
-                "gray" => Color::Gray,
-                "darkgray" => Color::DarkGray,
-                "lightred" => Color::LightRed,
-                "lightgreen" => Color::LightGreen,
-                "lightyellow" => Color::LightYellow,
-                "lightblue" => Color::LightBlue,
-                "lightmagenta" => Color::LightMagenta,
-                "lightcyan" => Color::LightCyan,
-                "white" => Color::White,
-                _ => Color::White,
+        out
+    }
+
+    fn decode(s: &str) -> PathBuf {
+        let bytes = s.as_bytes();
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'%' && i + 3 <= bytes.len() {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                if let Ok(b) = u8::from_str_radix(hex, 16) {
+                    out.push(b);
+                    i += 3;
+                    continue;
+                }
+            }
+            out.push(bytes[i]);
+            i += 1;
+        }
+        PathBuf::from(OsString::from_vec(out))
+    }
+
+    // Move a file or directory into the trash
+    // Returns the name the item was given inside the trash
+    pub async fn put(
+        path: &Path,
+        opts: fs_ops::CopyOpts,
+        progress: &aq::Reporter,
+    ) -> Result<String, String> {
+        let path = std::path::absolute(path).map_err(|e| e.to_string())?;
+        let file_name = match path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => return Err(format!("Invalid path: {}", path.to_string_lossy())),
+        };
+        fs::create_dir_all(files_dir())
+            .await
+            .map_err(|e| e.to_string())?;
+        fs::create_dir_all(info_dir())
+            .await
+            .map_err(|e| e.to_string())?;
+        let info = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode(&path),
+            chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+        );
+        // Reserve a unique name by creating the info file first
+        let mut name = file_name.clone();
+        let mut n = 1;
+        loop {
+            let reserved = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(info_path(&name))
+                .await;
+            match reserved {
+                Ok(_) if !files_dir().join(&name).exists() => break,
+                Ok(_) => {
+                    let _ = fs::remove_file(info_path(&name)).await;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.to_string()),
+            }
+            n += 1;
+            name = format!("{}.{}", file_name, n);
+        }
+        if let Err(e) = fs::write(info_path(&name), info).await {
+            let _ = fs::remove_file(info_path(&name)).await;
+            return Err(e.to_string());
+        }
+        // Items on other filesystems are copied into the home trash
+        match fs_ops::move_any(&path, &files_dir().join(&name), opts, progress).await {
+            Ok(_) => Ok(name),
+            Err(e) => {
+                let _ = fs::remove_file(info_path(&name)).await;
+                Err(e.to_string())
+            }
+        }
+    }
+
+    // Read every item in the trash, newest first
+    pub fn list() -> Vec<TrashItem> {
+        let mut items = Vec::new();
+        let read_dir = match std::fs::read_dir(info_dir()) {
+            Ok(rd) => rd,
+            Err(_) => return items,
+        };
+        for entry in read_dir.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let name = match file_name.strip_suffix(".trashinfo") {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let content = match std::fs::read_to_string(entry.path()) {
+                Ok(content) => content,
+                Err(_) => continue,
+            };
+            let mut orig = PathBuf::new();
+            let mut deleted = String::new();
+            for line in content.lines() {
+                if let Some(value) = line.strip_prefix("Path=") {
+                    orig = decode(value);
+                } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+                    deleted = value.to_string();
+                }
             }
+            items.push(TrashItem {
+                name,
+                orig,
+                deleted,
+            });
         }
+        items.sort_by(|a, b| b.deleted.cmp(&a.deleted));
+        items
+    }
 
-        pub fn get_path() -> std::path::PathBuf {
-            let cs_path = dirs::config_dir()
-                .unwrap_or(std::env::current_dir().unwrap())
-                .join(crate::APP_NAME)
-                .join(FILE_NAME);
-            cs_path
+    // Move a trashed item back to where it came from
+    pub async fn restore(
+        name: &str,
+        opts: fs_ops::CopyOpts,
+        progress: &aq::Reporter,
+    ) -> Result<PathBuf, String> {
+        let item = match list().into_iter().find(|item| item.name == name) {
+            Some(item) => item,
+            None => return Err(format!("No trash item named '{}'", name)),
+        };
+        if item.orig.exists() {
+            return Err(format!(
+                "{} already exists, not overwriting",
+                item.orig.to_string_lossy()
+            ));
         }
+        if let Some(parent) = item.orig.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        fs_ops::move_any(&files_dir().join(&item.name), &item.orig, opts, progress)
+            .await
+            .map_err(|e| e.to_string())?;
+        let _ = fs::remove_file(info_path(&item.name)).await;
+        Ok(item.orig)
+    }
 
-        pub fn make_list_auto() -> Colors {
-            // Read colors.txt
-            let colors = match fs::read_to_string(crate::APP_NAME.to_string() + FILE_NAME) {
-                Ok(content) => content,
-                Err(_) => {
-                    log!("colors.txt not found, using default colorscheme");
-                    return Colors::make_list(DEFAULT);
-                }
+    // Permanently remove everything in the trash
+    // Returns the number of items removed
+    pub async fn empty() -> Result<usize, String> {
+        let mut count = 0;
+        for item in list() {
+            let path = files_dir().join(&item.name);
+            let res = match fs::symlink_metadata(&path).await {
+                Ok(meta) if meta.is_dir() => fs::remove_dir_all(&path).await,
+                Ok(_) => fs::remove_file(&path).await,
+                Err(_) => Ok(()), // Orphaned info file
             };
-            Colors::make_list(&colors)
+            res.map_err(|e| format!("Failed to remove {}: {}", path.to_string_lossy(), e))?;
+            let _ = fs::remove_file(info_path(&item.name)).await;
+            count += 1;
         }
+        Ok(count)
+    }
 
-        fn make_list(colors_str: &str) -> Colors {
-            let mut colors = Colors::new();
-            for line in colors_str.lines() {
-                // Ignore comments
-                if line.starts_with('#') || line.trim().is_empty() {
-                    continue;
-                }
-                // Trim whitespace
-                let line = line.trim();
-                let split = line.split_whitespace().collect::<Vec<&str>>();
-                if split.len() != 2 {
-                    continue;
-                }
-                let key = split[0];
-                let value = split[1];
-                match key {
-                    "name" => colors.name = value.to_string(),
-                    "search_border" => {
-                        colors.search_border = Colors::from_str(value);
-                    }
-                    "preview_border" => {
-                        colors.preview_border = Colors::from_str(value);
-                    }
-                    "listing_border" => {
-                        colors.listing_border = Colors::from_str(value);
-                    }
-                    "file" => {
-                        colors.file = Colors::from_str(value);
-                    }
-                    "dir" => {
-                        colors.dir = Colors::from_str(value);
-                    }
-                    "command" => {
-                        colors.command = Colors::from_str(value);
-                    }
-                    "executable" => {
-                        colors.executable = Colors::from_str(value);
-                    }
-                    "shortcut" => {
-                        colors.shortcut = Colors::from_str(value);
-                    }
-                    "image" => {
-                        colors.image = Colors::from_str(value);
-                    }
-                    "info" => {
-                        colors.info = Colors::from_str(value);
-                    }
-                    "tip" => {
-                        colors.tip = Colors::from_str(value);
-                    }
-                    "warning" => {
-                        colors.warning = Colors::from_str(value);
-                    }
-                    "error" => {
-                        colors.error = Colors::from_str(value);
-                    }
-                    "ok" => {
-                        colors.ok = Colors::from_str(value);
-                    }
-                    "header" => {
-                        colors.header = Colors::from_str(value);
-                    }
-                    "hi" => {
-                        colors.hi = Colors::from_str(value);
-                    }
-                    "dim" => {
-                        colors.dim = Colors::from_str(value);
-                    }
-                    "misc" => {
-                        colors.misc = Colors::from_str(value);
-                    }
-                    _ => {}
-                }
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn raw(bytes: &[u8]) -> PathBuf {
+            PathBuf::from(OsString::from_vec(bytes.to_vec()))
+        }
+
+        #[test]
+        fn encode_keeps_unreserved_bytes() {
+            assert_eq!(encode(Path::new("/home/me/a-b_c.~1")), "/home/me/a-b_c.~1");
+        }
+
+        #[test]
+        fn encode_escapes_everything_else() {
+            assert_eq!(encode(Path::new("/tmp/a b%")), "/tmp/a%20b%25");
+            assert_eq!(encode(&raw(b"/tmp/\xff")), "/tmp/%FF");
+            assert_eq!(encode(Path::new("/ü")), "/%C3%BC");
+        }
+
+        #[test]
+        fn decode_round_trips() {
+            for path in [
+                raw(b"/tmp/a b"),
+                raw(b"/x/\xff\xfe%"),
+                raw("/ü/ñ".as_bytes()),
+            ] {
+                assert_eq!(decode(&encode(&path)), path);
             }
-            colors
+        }
+
+        #[test]
+        fn decode_trailing_escape() {
+            assert_eq!(decode("/tmp/a%20"), PathBuf::from("/tmp/a "));
+        }
+
+        #[test]
+        fn decode_keeps_broken_escapes() {
+            assert_eq!(decode("/tmp/100%"), PathBuf::from("/tmp/100%"));
+            assert_eq!(decode("/tmp/%2"), PathBuf::from("/tmp/%2"));
+            assert_eq!(decode("/tmp/%zz"), PathBuf::from("/tmp/%zz"));
         }
     }
 }
 
-// Keybinding management
-mod kb {
-    use super::cmd_data;
-    use super::cmd_data::CmdName;
-    use crate::{APP_NAME, log};
-    use crossterm::event::{KeyCode, KeyModifiers};
-    use std::{env, fs, path::PathBuf};
+mod util {
+    use std::path::{Path, PathBuf};
 
-    const FILE_NAME: &str = "keybinds.txt";
-    pub const DEFAULT: &str = r#"
-#
-# Default keybinds
-#
+    pub fn cls() {
+        println!("\x1B[2J\x1B[1;1H");
+    }
 
-exit           esc
-exit           ctrl-q
-home           alt-g
-cur-up         up
-cur-up         ctrl-k
-cur-down       down
-cur-down       ctrl-j
-sel            tab
-dir-up         ctrl-h
-dir-up         left
-dir-back       ctrl-u
-explode        ctrl-x
-edit           ctrl-e
-goto           ctrl-g
-enter          enter
-enter          right
-enter          ctrl-l
-cmd-win        ctrl-w
-cmd-find       ctrl-t
-cmd-list       ctrl-i
-sec-up         alt-k
-sec-up         ctrl-up
-sec-down       alt-j
-sec-down       ctrl-down
-input-clear    ctrl-z
-shell          ctrl-s
-os-open        ctrl-o
-hidden-toggle  alt-h
-"#;
-    #[derive(Clone)]
-    pub struct KeyBind {
-        pub modifiers: KeyModifiers,
-        pub code: KeyCode,
-        pub command: CmdName,
+    pub fn fpath(path: &PathBuf) -> String {
+        let pstring = path.to_str().unwrap().to_string();
+        let home = dirs::home_dir().unwrap();
+        let home_str = home.to_str().unwrap();
+        if pstring.starts_with(home_str) {
+            return pstring.replacen(home_str, "~", 1);
+        }
+        pstring
     }
-    impl KeyBind {
-        fn new(modifiers: KeyModifiers, code: KeyCode, command: CmdName) -> Self {
-            Self {
-                modifiers,
-                code,
-                command,
+
+    // Create a new directory in parent which only we can access
+    // Named after the app, the pid and a counter so parallel tasks never share one
+    pub fn private_dir(parent: &Path, purpose: &str) -> std::io::Result<PathBuf> {
+        use std::os::unix::fs::DirBuilderExt;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let path = parent.join(format!(
+                ".{}-{}-{}-{}",
+                crate::APP_NAME,
+                purpose,
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            match std::fs::DirBuilder::new().mode(0o700).create(&path) {
+                Ok(_) => return Ok(path),
+                // Left over from an earlier run with the same pid
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
             }
         }
     }
-    pub type KeyBindList = Vec<KeyBind>;
 
-    // Unused
-    // pub fn to_strings(kb: &KeyBind) -> (String, String) {
-    //     let modifier = match kb.modifiers {
-    //         KeyModifiers::ALT => "alt",
-    //         KeyModifiers::CONTROL => "ctrl",
-    //         KeyModifiers::SHIFT => "shift",
-    //         KeyModifiers::NONE => "none",
-    //         _ => "UNKNOWN",
-    //     };
-    //     let code_str = match &kb.code {
-    //         KeyCode::Char(c) => c.to_string(),
-    //         _ => kb.code.to_string(),
-    //     };
-    //     (modifier.to_string(), code_str.to_lowercase())
-    // }
+    // Format a byte count like "1.5 MiB"
+    pub fn human_size(bytes: u64) -> String {
+        const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+        let mut size = bytes as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        if unit == 0 {
+            format!("{} {}", bytes, UNITS[0])
+        } else {
+            format!("{:.1} {}", size, UNITS[unit])
+        }
+    }
 
-    // Short string like "ctrl-a"
-    pub fn to_string_short(kb: &KeyBind) -> String {
-        let modifier = match kb.modifiers {
-            KeyModifiers::ALT => "alt",
-            KeyModifiers::CONTROL => "ctrl",
-            KeyModifiers::SHIFT => "shift",
-            KeyModifiers::NONE => "",
-            _ => "UNKNOWN",
+    // Format how long ago a time was like "5m ago" or "3d ago"
+    pub fn relative_time(time: std::time::SystemTime) -> String {
+        let secs = match std::time::SystemTime::now().duration_since(time) {
+            Ok(elapsed) => elapsed.as_secs(),
+            Err(_) => return "future".to_string(),
         };
-        if modifier.is_empty() {
-            return format!("{}", kb.code.to_string().to_lowercase());
+        match secs {
+            0..60 => "just now".to_string(),
+            60..3600 => format!("{}m ago", secs / 60),
+            3600..86400 => format!("{}h ago", secs / 3600),
+            86400..2592000 => format!("{}d ago", secs / 86400),
+            2592000..31536000 => format!("{}mo ago", secs / 2592000),
+            _ => format!("{}y ago", secs / 31536000),
         }
-        return format!("{}-{}", modifier, kb.code.to_string().to_lowercase());
     }
 
-    // Full string with command name
-    pub fn to_string_full(cmd_list: &cmd_data::CmdList, kb: &KeyBind) -> String {
-        return format!(
-            "{:<12} {}\n",
-            cmd_data::get_cmd(cmd_list, &kb.command),
-            to_string_short(kb)
-        );
+    // Unix permission bits like "rwxr-xr-x"
+    pub fn perm_string(mode: u32) -> String {
+        let mut out = String::new();
+        for (i, shift) in [6, 3, 0].iter().enumerate() {
+            let bits = (mode >> shift) & 0o7;
+            out.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+            out.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+            // setuid, setgid and sticky replace the x of their group
+            let special = mode & (0o4000 >> i) != 0;
+            let (on, off) = match (i, special) {
+                (2, true) => ('t', 'T'),
+                (_, true) => ('s', 'S'),
+                _ => ('x', '-'),
+            };
+            out.push(if bits & 0o1 != 0 { on } else { off });
+        }
+        out
     }
 
-    pub fn find_by_cmd(keybinds: &KeyBindList, cmd: &cmd_data::CmdName) -> Option<KeyBind> {
-        for kb in keybinds.iter() {
-            if &kb.command == cmd {
-                return Some(kb.clone());
+    // Names from a passwd or group style file, read once
+    fn id_names(path: &str) -> std::collections::HashMap<u32, String> {
+        std::fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(':');
+                let name = fields.next()?;
+                let id = fields.nth(1)?.parse::<u32>().ok()?;
+                Some((id, name.to_string()))
+            })
+            .collect()
+    }
+
+    // User name of a uid, the number if it has no name
+    pub fn user_name(uid: u32) -> String {
+        static USERS: std::sync::OnceLock<std::collections::HashMap<u32, String>> =
+            std::sync::OnceLock::new();
+        USERS
+            .get_or_init(|| id_names("/etc/passwd"))
+            .get(&uid)
+            .cloned()
+            .unwrap_or(uid.to_string())
+    }
+
+    // Group name of a gid, the number if it has no name
+    pub fn group_name(gid: u32) -> String {
+        static GROUPS: std::sync::OnceLock<std::collections::HashMap<u32, String>> =
+            std::sync::OnceLock::new();
+        GROUPS
+            .get_or_init(|| id_names("/etc/group"))
+            .get(&gid)
+            .cloned()
+            .unwrap_or(gid.to_string())
+    }
+
+    // Format an elapsed time like "4.2s" or "3m 05s"
+    pub fn human_duration(duration: std::time::Duration) -> String {
+        let secs = duration.as_secs();
+        if secs < 60 {
+            format!("{:.1}s", duration.as_secs_f32())
+        } else if secs < 3600 {
+            format!("{}m {:02}s", secs / 60, secs % 60)
+        } else {
+            format!("{}h {:02}m", secs / 3600, (secs / 60) % 60)
+        }
+    }
+
+    // Binary content has NUL bytes or is not valid UTF-8
+    // A character cut off at the end of the block does not count
+    pub fn is_binary(block: &[u8]) -> bool {
+        if block.contains(&0) {
+            return true;
+        }
+        match std::str::from_utf8(block) {
+            Ok(_) => false,
+            Err(e) => e.error_len().is_some(),
+        }
+    }
+
+    // Text progress bar like "[#####-----]"
+    pub fn progress_bar(percent: u16, width: usize) -> String {
+        let filled = (percent.min(100) as usize * width) / 100;
+        format!("[{}{}]", "#".repeat(filled), "-".repeat(width - filled))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::time::{Duration, SystemTime};
+
+        #[test]
+        fn permissions() {
+            assert_eq!(perm_string(0o755), "rwxr-xr-x");
+            assert_eq!(perm_string(0o644), "rw-r--r--");
+            assert_eq!(perm_string(0o100600), "rw-------");
+        }
+
+        #[test]
+        fn special_bits() {
+            assert_eq!(perm_string(0o4755), "rwsr-xr-x");
+            assert_eq!(perm_string(0o4644), "rwSr--r--");
+            assert_eq!(perm_string(0o2750), "rwxr-s---");
+            assert_eq!(perm_string(0o1777), "rwxrwxrwt");
+            assert_eq!(perm_string(0o1776), "rwxrwxrwT");
+        }
+
+        #[test]
+        fn relative_times() {
+            let ago = |secs: u64| relative_time(SystemTime::now() - Duration::from_secs(secs));
+            assert_eq!(ago(10), "just now");
+            assert_eq!(ago(120), "2m ago");
+            assert_eq!(ago(3 * 3600), "3h ago");
+            assert_eq!(ago(2 * 86400), "2d ago");
+            assert_eq!(ago(60 * 86400), "2mo ago");
+            assert_eq!(ago(800 * 86400), "2y ago");
+            let later = SystemTime::now() + Duration::from_secs(3600);
+            assert_eq!(relative_time(later), "future");
+        }
+    }
+}
+
+// Filter tokens mixed with fuzzy terms in the search box
+// e.g. "ext:rs size:>1M mod:<7d type:dir !test main"
+mod query {
+    use crate::node_info::{NodeInfo, NodeType};
+    use std::path::Path;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[derive(Clone, Copy, PartialEq)]
+    pub enum Cmp {
+        Less,
+        LessEq,
+        Greater,
+        GreaterEq,
+        Equal,
+    }
+    impl Cmp {
+        // Split the comparison off the front of a value like ">=1M"
+        fn split(value: &str) -> (Cmp, &str) {
+            for (prefix, cmp) in [
+                (">=", Cmp::GreaterEq),
+                ("<=", Cmp::LessEq),
+                (">", Cmp::Greater),
+                ("<", Cmp::Less),
+                ("=", Cmp::Equal),
+            ] {
+                if let Some(rest) = value.strip_prefix(prefix) {
+                    return (cmp, rest);
+                }
             }
+            (Cmp::Equal, value)
         }
-        None
+        fn test(self, a: u64, b: u64) -> bool {
+            match self {
+                Cmp::Less => a < b,
+                Cmp::LessEq => a <= b,
+                Cmp::Greater => a > b,
+                Cmp::GreaterEq => a >= b,
+                Cmp::Equal => a == b,
+            }
+        }
+    }
+
+    #[derive(Clone, PartialEq)]
+    pub enum Filter {
+        Ext(Vec<String>),    // ext:rs,toml
+        Size(Cmp, u64),      // size:>1M
+        Modified(Cmp, u64),  // mod:<7d, compares the age in seconds
+        Type(Vec<NodeType>), // type:dir
+        Not(String),         // !test, names containing this are dropped
+    }
+
+    #[derive(Clone, Default)]
+    pub struct Query {
+        pub terms: String, // Everything which is not a filter, for the fuzzy matcher
+        pub filters: Vec<Filter>,
+    }
+
+    // "1.5M" as bytes, units are powers of 1024
+    fn parse_size(value: &str) -> Option<u64> {
+        let lower = value.to_lowercase();
+        let lower = lower.trim_end_matches("ib").trim_end_matches('b');
+        let split = lower
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(lower.len());
+        let (num, unit) = lower.split_at(split);
+        let mult: u64 = match unit {
+            "" => 1,
+            "k" => 1 << 10,
+            "m" => 1 << 20,
+            "g" => 1 << 30,
+            "t" => 1 << 40,
+            _ => return None,
+        };
+        let num = num.parse::<f64>().ok()?;
+        Some((num * mult as f64) as u64)
     }
 
-    pub fn get_path() -> PathBuf {
-        let kb_path = dirs::config_dir()
-            .unwrap_or(env::current_dir().unwrap())
-            .join(APP_NAME)
-            .join(FILE_NAME);
-        kb_path
+    // "7d" as seconds
+    fn parse_age(value: &str) -> Option<u64> {
+        let split = value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len());
+        let (num, unit) = value.split_at(split);
+        let mult = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "" | "d" => 60 * 60 * 24,
+            "w" => 60 * 60 * 24 * 7,
+            "y" => 60 * 60 * 24 * 365,
+            _ => return None,
+        };
+        Some(num.parse::<u64>().ok()? * mult)
     }
 
-    pub fn make_list_auto() -> KeyBindList {
-        // Read keybinds.txt
-        let keybinds = match fs::read_to_string(get_path()) {
-            Ok(content) => content,
-            Err(_) => {
-                log!("{} not found, using default keybinds", FILE_NAME);
-                return make_list(DEFAULT);
+    fn parse_type(value: &str) -> Option<Vec<NodeType>> {
+        Some(match value {
+            "dir" | "d" => vec![NodeType::Directory],
+            "file" | "f" => vec![NodeType::File, NodeType::Executable, NodeType::Image],
+            "exe" | "x" => vec![NodeType::Executable],
+            "image" | "img" => vec![NodeType::Image],
+            "link" | "l" => vec![NodeType::Symlink],
+            _ => return None,
+        })
+    }
+
+    // None if the token is a plain search term
+    // Some(None) if it looks like a filter but can't be used
+    fn parse_token(token: &str) -> Option<Option<Filter>> {
+        if let Some(term) = token.strip_prefix('!') {
+            return Some((!term.is_empty()).then(|| Filter::Not(term.to_lowercase())));
+        }
+        let (key, value) = token.split_once(':')?;
+        let filter = match key {
+            "ext" => {
+                let exts = value
+                    .split(',')
+                    .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                    .filter(|ext| !ext.is_empty())
+                    .collect::<Vec<_>>();
+                (!exts.is_empty()).then_some(Filter::Ext(exts))
+            }
+            "size" => {
+                let (cmp, value) = Cmp::split(value);
+                parse_size(value).map(|size| Filter::Size(cmp, size))
+            }
+            "mod" => {
+                let (cmp, value) = Cmp::split(value);
+                parse_age(value).map(|age| Filter::Modified(cmp, age))
             }
+            "type" => parse_type(value).map(Filter::Type),
+            // Unknown keys are just part of a name
+            _ => return None,
         };
-        make_list(&keybinds)
+        Some(filter)
     }
 
-    fn make_list(keybinds_str: &str) -> KeyBindList {
-        let mut list = KeyBindList::new();
-        let cmd_list = cmd_data::make_cmd_list();
-        for line in keybinds_str.lines() {
-            // Ignore comments
-            if line.starts_with('#') || line.trim().is_empty() {
-                continue;
+    // Whether a token looks like a filter but is not valid
+    pub fn is_invalid(token: &str) -> bool {
+        matches!(parse_token(token), Some(None))
+    }
+
+    // Invalid filters are left out
+    pub fn parse(input: &str) -> Query {
+        let mut query = Query::default();
+        let mut terms = Vec::new();
+        for token in input.split_whitespace() {
+            match parse_token(token) {
+                Some(Some(filter)) => query.filters.push(filter),
+                Some(None) => {}
+                None => terms.push(token),
             }
-            // Trim whitespace
-            let line = line.trim();
-            let split = line.split_whitespace().collect::<Vec<&str>>();
-            if split.len() != 2 {
-                log!("Invalid line in {}: {}", FILE_NAME, line);
-                continue;
+        }
+        query.terms = terms.join(" ");
+        query
+    }
+
+    impl Query {
+        // Whether the node passes every filter, dir is where the node is listed
+        pub fn keep(&self, node: &NodeInfo, dir: &Path) -> bool {
+            if self.filters.is_empty() {
+                return true;
             }
-            let cmd = split[0];
-            let combo = split[1];
-            let (modifier, code) = if combo.contains('-') {
-                let combo_split = combo.splitn(2, '-').collect::<Vec<&str>>();
-                (combo_split[0], combo_split[1])
-            } else {
-                ("none", combo)
-            };
-            let cmd = match cmd_data::cmd_name_from_str(&cmd_list, cmd) {
-                Some(name) => name,
-                None => {
-                    log!("Unknown command in {}: {}", FILE_NAME, cmd);
-                    continue;
-                }
-            };
-            let modifiers = match modifier.to_lowercase().as_str() {
-                "ctrl" => KeyModifiers::CONTROL,
-                "alt" => KeyModifiers::ALT,
-                "shift" => KeyModifiers::SHIFT,
-                "none" => KeyModifiers::NONE,
-                _ => {
-                    log!("Unknown modifier in {}: {}", FILE_NAME, modifier);
-                    continue;
-                }
+            let name = node.name.to_lowercase();
+            // Size and mtime from the listing, symlinks are filtered by their targets
+            // Anything without a stat is read once when a filter needs it
+            let mut stat = node
+                .stat
+                .as_ref()
+                .filter(|_| !node.is_symlink())
+                .map(|stat| (stat.size, stat.modified));
+            let mut size_and_modified = || {
+                *stat.get_or_insert_with(|| {
+                    let path = dir.join(&node.name);
+                    // Broken symlinks are filtered by the link itself
+                    std::fs::metadata(&path)
+                        .or_else(|_| std::fs::symlink_metadata(&path))
+                        .map(|meta| (meta.len(), meta.modified().ok()))
+                        .unwrap_or((0, None))
+                })
             };
-            let code = match code.to_lowercase().as_str() {
-                "enter" => KeyCode::Enter,
-                "esc" => KeyCode::Esc,
-                "up" => KeyCode::Up,
-                "down" => KeyCode::Down,
-                "left" => KeyCode::Left,
-                "right" => KeyCode::Right,
-                "tab" => KeyCode::Tab,
-                "backspace" => KeyCode::Backspace,
-                "home" => KeyCode::Home,
-                "end" => KeyCode::End,
-                "pageup" => KeyCode::PageUp,
-                "pagedown" => KeyCode::PageDown,
-                "space" => KeyCode::Char(' '),
-                c if c.len() == 1 => {
-                    let ch = c.chars().next().unwrap();
-                    KeyCode::Char(ch)
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            for filter in self.filters.iter() {
+                // Only negations apply to shortcuts and commands
+                if !matches!(filter, Filter::Not(_)) && !node.is_file_like() {
+                    return false;
                 }
-                _ => {
-                    log!("Unknown key code in {}: {}", FILE_NAME, code);
-                    continue;
+                let keep = match filter {
+                    Filter::Not(term) => !name.contains(term.as_str()),
+                    Filter::Ext(exts) => Path::new(&name)
+                        .extension()
+                        .is_some_and(|ext| exts.iter().any(|e| e.as_str() == ext)),
+                    Filter::Type(types) => types.contains(&node.node_type),
+                    Filter::Size(cmp, size) => cmp.test(size_and_modified().0, *size),
+                    Filter::Modified(cmp, age) => {
+                        let modified = size_and_modified()
+                            .1
+                            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                            .map_or(0, |duration| duration.as_secs());
+                        cmp.test(now.saturating_sub(modified), *age)
+                    }
+                };
+                if !keep {
+                    return false;
                 }
-            };
-            let keybind = KeyBind::new(modifiers, code, cmd);
-            list.push(keybind);
+            }
+            true
         }
-        list
     }
-}
-
-// General configuration management
-mod cfg {
-    use std::fs;
-
-    const FILE_NAME: &str = "config.txt";
-    pub const DEFAULT: &str = r#"
-#
-# Default configuration
-#
-
-# Command to run when you 'enter' on a file/directory
-cmd_on_enter     edit
 
-# How many items to show in a list - 0 = no limit
-list_limit       100
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn sizes() {
+            assert_eq!(parse_size("10"), Some(10));
+            assert_eq!(parse_size("2k"), Some(2048));
+            assert_eq!(parse_size("2KB"), Some(2048));
+            assert_eq!(parse_size("1.5M"), Some(1536 * 1024));
+            assert_eq!(parse_size("1GiB"), Some(1 << 30));
+            assert_eq!(parse_size("3x"), None);
+            assert_eq!(parse_size("M"), None);
+            assert_eq!(parse_size(""), None);
+        }
 
-# How many items can be searched at once - 0 = no limit
-find_limit       0
+        #[test]
+        fn ages() {
+            assert_eq!(parse_age("30s"), Some(30));
+            assert_eq!(parse_age("2h"), Some(7200));
+            assert_eq!(parse_age("7d"), Some(7 * 86400));
+            assert_eq!(parse_age("3"), Some(3 * 86400));
+            assert_eq!(parse_age("1w"), Some(7 * 86400));
+            assert_eq!(parse_age("5q"), None);
+            assert_eq!(parse_age("d"), None);
+            assert_eq!(parse_age("1.5d"), None);
+        }
 
-# How many lines to preview - 0 = no limit
-preview_limit    100
+        #[test]
+        fn plain_terms_are_not_filters() {
+            assert!(parse_token("main").is_none());
+            assert!(parse_token("http://host").is_none());
+        }
 
-# Whether to force sixel image rendering (if terminal supports it)
-force_sixel      false
+        #[test]
+        fn filters() {
+            let ext = Filter::Ext(vec!["rs".to_string(), "toml".to_string()]);
+            assert!(parse_token("ext:rs,.TOML") == Some(Some(ext)));
+            assert!(parse_token("size:>=1k") == Some(Some(Filter::Size(Cmp::GreaterEq, 1024))));
+            assert!(parse_token("size:5") == Some(Some(Filter::Size(Cmp::Equal, 5))));
+            assert!(parse_token("mod:<2h") == Some(Some(Filter::Modified(Cmp::Less, 7200))));
+            let dir = Filter::Type(vec![NodeType::Directory]);
+            assert!(parse_token("type:dir") == Some(Some(dir)));
+            assert!(parse_token("!Test") == Some(Some(Filter::Not("test".to_string()))));
+        }
 
-# Maximum image width in characters
-max_image_width  80
+        #[test]
+        fn invalid_filters() {
+            assert!(parse_token("size:big") == Some(None));
+            assert!(parse_token("mod:soon") == Some(None));
+            assert!(parse_token("type:banana") == Some(None));
+            assert!(parse_token("ext:") == Some(None));
+            assert!(parse_token("!") == Some(None));
+            assert!(is_invalid("size:big"));
+            assert!(!is_invalid("size:1M"));
+        }
 
-# Responsive breakpoint in characters
-responsive_break 96
+        #[test]
+        fn parse_splits_terms_from_filters() {
+            let query = parse("foo ext:rs bar size:huge");
+            assert_eq!(query.terms, "foo bar");
+            assert!(query.filters == [Filter::Ext(vec!["rs".to_string()])]);
+        }
+    }
+}
 
-# Input polling interval in milliseconds
-# Higher value = lower CPU usage, lower value = more responsive input
-input_poll       10
+// How search terms are matched against names
+mod matching {
+    use fuzzy_matcher::FuzzyMatcher;
+    use fuzzy_matcher::skim::SkimMatcherV2;
+    use regex::{Regex, RegexBuilder};
+    use std::path::Path;
 
-# Whether to show hidden files by default
-# show_hidden    true
-"#;
-    pub struct Config {
-        pub cmd_on_enter: String,
-        pub list_limit: u32,
-        pub find_limit: u32,
-        pub preview_limit: usize,
-        pub force_sixel: bool,
-        pub max_image_width: u16,
-        pub responsive_break: u16,
-        pub input_poll: u64,
-        pub show_hidden: bool,
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub enum Mode {
+        Fuzzy,
+        Substring,
+        Prefix, // Start of the file name
+        Regex,
+        Glob, // Whole file name, or the whole path if the pattern has a '/'
     }
-    impl Config {
-        pub fn new() -> Self {
-            Self {
-                cmd_on_enter: "edit".to_string(),
-                list_limit: 100,
-                find_limit: 0,
-                preview_limit: 100,
-                force_sixel: false,
-                max_image_width: 80,
-                responsive_break: 100,
-                input_poll: 10,
-                show_hidden: true,
+    const MODES: [Mode; 5] = [
+        Mode::Fuzzy,
+        Mode::Substring,
+        Mode::Prefix,
+        Mode::Regex,
+        Mode::Glob,
+    ];
+    impl Mode {
+        pub fn from_str(s: &str) -> Option<Mode> {
+            MODES
+                .iter()
+                .find(|mode| mode.as_str() == s.to_lowercase())
+                .copied()
+        }
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                Mode::Fuzzy => "fuzzy",
+                Mode::Substring => "substring",
+                Mode::Prefix => "prefix",
+                Mode::Regex => "regex",
+                Mode::Glob => "glob",
             }
         }
-        pub fn get_path() -> std::path::PathBuf {
-            let cfg_path = dirs::config_dir()
-                .unwrap_or(std::env::current_dir().unwrap())
-                .join(crate::APP_NAME)
-                .join(FILE_NAME);
-            cfg_path
+        pub fn next(&self) -> Mode {
+            let i = MODES.iter().position(|mode| mode == self).unwrap_or(0);
+            MODES[(i + 1) % MODES.len()]
         }
-        pub fn make_list_auto() -> Config {
-            // Read config.txt
-            let config = match fs::read_to_string(crate::APP_NAME.to_string() + FILE_NAME) {
-                Ok(content) => content,
-                Err(_) => {
-                    crate::log!("{} not found, using default configuration", FILE_NAME);
-                    return Config::make_list(DEFAULT);
-                }
-            };
-            Config::make_list(&config)
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub enum Case {
+        Smart, // Ignore case unless the pattern has uppercase letters
+        Sensitive,
+        Insensitive,
+    }
+    impl Case {
+        pub fn from_str(s: &str) -> Option<Case> {
+            match s.to_lowercase().as_str() {
+                "smart" => Some(Case::Smart),
+                "sensitive" => Some(Case::Sensitive),
+                "insensitive" => Some(Case::Insensitive),
+                _ => None,
+            }
         }
-        fn make_list(cfg_str: &str) -> Config {
-            let mut config = Config::new();
-            for line in cfg_str.lines() {
-                // Ignore comments
-                if line.starts_with('#') || line.trim().is_empty() {
-                    continue;
-                }
-                // Trim whitespace
-                let line = line.trim();
-                let split = line.split_whitespace().collect::<Vec<&str>>();
-                if split.len() != 2 {
-                    continue;
+    }
+
+    // "*.rs" as an anchored regex, '*' and '?' stop at '/' and "**" does not
+    fn glob_to_regex(glob: &str) -> String {
+        let mut re = String::from("^");
+        let mut chars = glob.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    re.push_str(".*");
                 }
-                let key = split[0];
-                let value = split[1];
-                match key {
-                    "cmd_on_enter" => config.cmd_on_enter = value.to_string(),
-                    "list_limit" => {
-                        if let Ok(limit) = value.parse::<u32>() {
-                            config.list_limit = if limit == 0 { u32::MAX } else { limit };
-                        }
-                    }
-                    "find_limit" => {
-                        if let Ok(limit) = value.parse::<u32>() {
-                            config.find_limit = if limit == 0 { u32::MAX } else { limit };
-                        }
-                    }
-                    "preview_limit" => {
-                        if let Ok(limit) = value.parse::<usize>() {
-                            config.preview_limit = if limit == 0 { usize::MAX } else { limit };
-                        }
-                    }
-                    "force_sixel" => {
-                        if value.to_lowercase() == "true" {
-                            config.force_sixel = true;
-                        } else {
-                            config.force_sixel = false;
-                        }
-                    }
-                    "max_image_width" => {
-                        if let Ok(width) = value.parse::<u16>() {
-                            config.max_image_width = width;
-                        }
+                '*' => re.push_str("[^/]*"),
+                '?' => re.push_str("[^/]"),
+                '[' => {
+                    let class = chars.clone().take_while(|c| *c != ']').collect::<String>();
+                    if class.is_empty() || chars.clone().nth(class.chars().count()).is_none() {
+                        re.push_str(r"\[");
+                        continue;
                     }
-                    "responsive_break" => {
-                        if let Ok(breakpoint) = value.parse::<u16>() {
-                            config.responsive_break = breakpoint;
-                        }
+                    for _ in 0..=class.chars().count() {
+                        chars.next();
                     }
-                    "input_poll" => {
-                        if let Ok(poll) = value.parse::<u64>() {
-                            config.input_poll = poll;
-                        }
+                    let class = class.replace('\\', r"\\");
+                    match class.strip_prefix('!') {
+                        Some(rest) => re.push_str(&format!("[^{}]", rest)),
+                        None => re.push_str(&format!("[{}]", class)),
                     }
-                    "show_hidden" => {
-                        if value.to_lowercase() == "true" {
-                            config.show_hidden = true;
-                        } else {
-                            config.show_hidden = false;
+                }
+                c => re.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        re.push('$');
+        re
+    }
+
+    fn file_name(text: &str) -> &str {
+        Path::new(text)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(text)
+    }
+
+    // Exploded paths are matched relative to the cwd
+    pub fn relative<'a>(name: &'a str, cwd: &Path) -> &'a str {
+        Path::new(name)
+            .strip_prefix(cwd)
+            .ok()
+            .and_then(|rel| rel.to_str())
+            .unwrap_or(name)
+    }
+
+    pub struct Matcher {
+        mode: Mode,
+        ignore_case: bool,
+        pattern: String,
+        skim: SkimMatcherV2,
+        regex: Option<Regex>,
+    }
+    impl Matcher {
+        // Fails if the pattern is not a valid regex or glob
+        pub fn new(mode: Mode, case: Case, pattern: &str) -> Result<Matcher, regex::Error> {
+            let ignore_case = match case {
+                Case::Smart => !pattern.chars().any(char::is_uppercase),
+                Case::Sensitive => false,
+                Case::Insensitive => true,
+            };
+            let skim = if ignore_case {
+                SkimMatcherV2::default().ignore_case()
+            } else {
+                SkimMatcherV2::default().respect_case()
+            };
+            let regex = match mode {
+                Mode::Regex => Some(pattern.to_string()),
+                Mode::Glob => Some(glob_to_regex(pattern)),
+                _ => None,
+            };
+            let regex = regex
+                .filter(|_| !pattern.is_empty())
+                .map(|re| RegexBuilder::new(&re).case_insensitive(ignore_case).build())
+                .transpose()?;
+            Ok(Matcher {
+                mode,
+                ignore_case,
+                pattern: pattern.to_string(),
+                skim,
+                regex,
+            })
+        }
+
+        fn has(&self, text: &str, f: fn(&str, &str) -> bool) -> bool {
+            if self.ignore_case {
+                f(&text.to_lowercase(), &self.pattern.to_lowercase())
+            } else {
+                f(text, &self.pattern)
+            }
+        }
+
+        // None if the text does not match
+        // Only the fuzzy mode ranks matches, the others keep the listing order
+        pub fn score(&self, text: &str) -> Option<i64> {
+            if self.pattern.is_empty() {
+                return Some(0);
+            }
+            let found = match self.mode {
+                Mode::Fuzzy => return self.skim.fuzzy_match(text, &self.pattern),
+                Mode::Substring => self.has(text, |t, p| t.contains(p)),
+                Mode::Prefix => self.has(file_name(text), |t, p| t.starts_with(p)),
+                Mode::Regex => self.regex.as_ref()?.is_match(text),
+                Mode::Glob if self.pattern.contains('/') => self.regex.as_ref()?.is_match(text),
+                Mode::Glob => self.regex.as_ref()?.is_match(file_name(text)),
+            };
+            found.then_some(0)
+        }
+
+        // Char positions in text which matched the pattern
+        pub fn indices(&self, text: &str) -> Vec<usize> {
+            if self.pattern.is_empty() {
+                return vec![];
+            }
+            let chars = |s: &str| s.chars().count();
+            let fold = |s: &str| {
+                if self.ignore_case {
+                    s.to_lowercase()
+                } else {
+                    s.to_string()
+                }
+            };
+            // Byte range in text to a range of char positions
+            let span =
+                |start: usize, end: usize| (chars(&text[..start])..chars(&text[..end])).collect();
+            let name_start = text.len() - file_name(text).len();
+            match self.mode {
+                Mode::Fuzzy => self
+                    .skim
+                    .fuzzy_indices(text, &self.pattern)
+                    .map(|(_, indices)| indices)
+                    .unwrap_or_default(),
+                Mode::Substring => {
+                    let folded = fold(text);
+                    match folded.find(&fold(&self.pattern)) {
+                        Some(i) => {
+                            let start = chars(&folded[..i]);
+                            (start..start + chars(&self.pattern)).collect()
                         }
+                        None => vec![],
                     }
-                    _ => {}
                 }
+                Mode::Prefix => {
+                    let start = chars(&text[..name_start]);
+                    (start..start + chars(&self.pattern)).collect()
+                }
+                Mode::Regex => match self.regex.as_ref().and_then(|re| re.find(text)) {
+                    Some(found) => span(found.start(), found.end()),
+                    None => vec![],
+                },
+                Mode::Glob if self.pattern.contains('/') => span(0, text.len()),
+                Mode::Glob => span(name_start, text.len()),
             }
-            config
         }
     }
-}
 
-// Shell command management
-mod shell_cmds {
-    use crate::cmd_data;
+    #[cfg(test)]
+    mod tests {
+        use super::*;
 
-    const FILE_NAME: &str = "shell_cmds.txt";
-    pub const DEFAULT: &str = r#"
-#
-# Default shell commands
-#
+        fn glob(pattern: &str) -> Regex {
+            Regex::new(&glob_to_regex(pattern)).unwrap()
+        }
 
-# A simple command
-ls -la
-# Ask for input (open prompt window)
-echo "you said" {ASK}
-# Echo the first selected file/directory
-echo $1
-# Create a zip archive of all selected files/directories
-zip -r archive.zip $...
-"#;
+        #[test]
+        fn translation() {
+            assert_eq!(glob_to_regex("*.rs"), r"^[^/]*\.rs$");
+            assert_eq!(glob_to_regex("a?c"), "^a[^/]c$");
+            assert_eq!(glob_to_regex("**/x"), "^.*/x$");
+            assert_eq!(glob_to_regex("[!a]b"), "^[^a]b$");
+            assert_eq!(glob_to_regex("[ab]"), "^[ab]$");
+        }
 
-    pub fn get_path() -> std::path::PathBuf {
-        let shell_cmds_path = dirs::config_dir()
-            .unwrap_or(std::env::current_dir().unwrap())
-            .join(crate::APP_NAME)
-            .join(FILE_NAME);
-        shell_cmds_path
+        #[test]
+        fn unclosed_class_is_literal() {
+            assert_eq!(glob_to_regex("[ab"), r"^\[ab$");
+            assert!(glob("[ab").is_match("[ab"));
+        }
+
+        #[test]
+        fn stars_and_slashes() {
+            assert!(glob("*.rs").is_match("main.rs"));
+            assert!(!glob("*.rs").is_match("src/main.rs"));
+            assert!(glob("src/**/*.rs").is_match("src/a/b/c.rs"));
+            assert!(!glob("a?c").is_match("a/c"));
+        }
+
+        #[test]
+        fn regex_characters_are_escaped() {
+            assert!(glob("a+b.(1)").is_match("a+b.(1)"));
+            assert!(!glob("a.b").is_match("axb"));
+        }
     }
+}
 
-    pub fn make_list_auto() -> Vec<String> {
-        // Read shell_cmds.txt
-        let shell_cmds = match std::fs::read_to_string(crate::APP_NAME.to_string() + FILE_NAME) {
-            Ok(content) => content,
-            Err(_) => {
-                crate::log!("shell_cmds.txt not found, using default shell commands");
-                return make_list(DEFAULT);
+// Ordering of directory listings
+mod sort {
+    use crate::node_info::{NodeInfo, NodeType};
+    use std::cmp::Ordering;
+    use std::fs;
+    use std::path::Path;
+    use std::time::SystemTime;
+
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub enum Key {
+        Name, // Natural order, "file2" before "file10"
+        Size,
+        Modified,
+        Ext,
+        Type,
+    }
+    const KEYS: [Key; 5] = [Key::Name, Key::Size, Key::Modified, Key::Ext, Key::Type];
+    impl Key {
+        pub fn from_str(s: &str) -> Option<Key> {
+            KEYS.iter()
+                .find(|key| key.as_str() == s.to_lowercase())
+                .copied()
+        }
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                Key::Name => "name",
+                Key::Size => "size",
+                Key::Modified => "mtime",
+                Key::Ext => "ext",
+                Key::Type => "type",
             }
-        };
-        make_list(&shell_cmds)
+        }
+        pub fn next(&self) -> Key {
+            let i = KEYS.iter().position(|key| key == self).unwrap_or(0);
+            KEYS[(i + 1) % KEYS.len()]
+        }
     }
 
-    pub fn make_list(shell_str: &str) -> Vec<String> {
-        let shell_cmd_name =
-            cmd_data::get_cmd(&cmd_data::make_cmd_list(), &cmd_data::CmdName::ShellQuick);
-        let mut list = Vec::new();
-        for line in shell_str.lines() {
-            // Ignore comments
-            if line.starts_with('#') || line.trim().is_empty() {
-                continue;
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub struct Sort {
+        pub key: Key,
+        pub desc: bool,
+        pub dirs_first: bool,
+    }
+    impl Sort {
+        pub fn new() -> Self {
+            Self {
+                key: Key::Name,
+                desc: false,
+                dirs_first: true,
             }
-            // Trim whitespace
-            let line = line.trim();
-            list.push(format!("{} {}", shell_cmd_name, line));
         }
-        list
+        // Short form for the listing title like "size desc"
+        pub fn as_string(&self) -> String {
+            let order = if self.desc { " desc" } else { "" };
+            format!("{}{}", self.key.as_str(), order)
+        }
     }
-}
 
-mod util {
-    use std::path::PathBuf;
+    // Compare digit runs by value so "2" comes before "10"
+    pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+        let mut a_chars = a.chars().peekable();
+        let mut b_chars = b.chars().peekable();
+        loop {
+            match (a_chars.peek().copied(), b_chars.peek().copied()) {
+                (None, None) => return a.cmp(b),
+                (None, Some(_)) => return Ordering::Less,
+                (Some(_), None) => return Ordering::Greater,
+                (Some(ac), Some(bc)) if ac.is_ascii_digit() && bc.is_ascii_digit() => {
+                    let mut a_num = String::new();
+                    while let Some(c) = a_chars.next_if(|c| c.is_ascii_digit()) {
+                        a_num.push(c);
+                    }
+                    let mut b_num = String::new();
+                    while let Some(c) = b_chars.next_if(|c| c.is_ascii_digit()) {
+                        b_num.push(c);
+                    }
+                    let a_trim = a_num.trim_start_matches('0');
+                    let b_trim = b_num.trim_start_matches('0');
+                    let ord = a_trim.len().cmp(&b_trim.len()).then(a_trim.cmp(b_trim));
+                    if ord != Ordering::Equal {
+                        return ord;
+                    }
+                }
+                (Some(ac), Some(bc)) => {
+                    let ord = ac.to_lowercase().cmp(bc.to_lowercase());
+                    if ord != Ordering::Equal {
+                        return ord;
+                    }
+                    a_chars.next();
+                    b_chars.next();
+                }
+            }
+        }
+    }
 
-    pub fn cls() {
-        println!("\x1B[2J\x1B[1;1H");
+    fn type_rank(node_type: &NodeType) -> u8 {
+        match node_type {
+            NodeType::Directory => 0,
+            NodeType::Symlink => 1,
+            NodeType::Executable => 2,
+            NodeType::Image => 3,
+            NodeType::File => 4,
+            _ => 5,
+        }
     }
 
-    pub fn fpath(path: &PathBuf) -> String {
-        let pstring = path.to_str().unwrap().to_string();
-        let home = dirs::home_dir().unwrap();
-        let home_str = home.to_str().unwrap();
-        if pstring.starts_with(home_str) {
-            return pstring.replacen(home_str, "~", 1);
+    fn ext(name: &str) -> String {
+        Path::new(name)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+    }
+
+    // Sort a listing of dir, metadata is read once per entry
+    pub fn apply(list: &mut Vec<NodeInfo>, dir: &Path, sort: Sort) {
+        let mut keyed: Vec<(u64, SystemTime, NodeInfo)> = list
+            .drain(..)
+            .map(|node| {
+                // Entries read from disk already carry their metadata
+                // That is the link's own, links sort by what they point to
+                let (size, modified) = match node.stat.as_ref().filter(|_| !node.is_symlink()) {
+                    Some(stat) => (stat.size, stat.modified),
+                    None => match fs::metadata(dir.join(&node.name)) {
+                        Ok(meta) => (meta.len(), meta.modified().ok()),
+                        Err(_) => (0, None),
+                    },
+                };
+                let size = if node.is_dir() { 0 } else { size };
+                let modified = modified.unwrap_or(SystemTime::UNIX_EPOCH);
+                (size, modified, node)
+            })
+            .collect();
+        keyed.sort_by(|a, b| {
+            let ord = match sort.key {
+                Key::Name => Ordering::Equal,
+                Key::Size => a.0.cmp(&b.0),
+                Key::Modified => a.1.cmp(&b.1),
+                Key::Ext => ext(&a.2.name).cmp(&ext(&b.2.name)),
+                Key::Type => type_rank(&a.2.node_type).cmp(&type_rank(&b.2.node_type)),
+            }
+            .then_with(|| natural_cmp(&a.2.name, &b.2.name));
+            let ord = if sort.desc { ord.reverse() } else { ord };
+            if sort.dirs_first {
+                b.2.is_dir().cmp(&a.2.is_dir()).then(ord)
+            } else {
+                ord
+            }
+        });
+        list.extend(keyed.into_iter().map(|(_, _, node)| node));
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn numbers_compare_by_value() {
+            assert_eq!(natural_cmp("file2", "file10"), Ordering::Less);
+            assert_eq!(natural_cmp("file10", "file9"), Ordering::Greater);
+            assert_eq!(natural_cmp("v1.10", "v1.9"), Ordering::Greater);
+        }
+
+        #[test]
+        fn case_is_ignored() {
+            assert_eq!(natural_cmp("apple", "Banana"), Ordering::Less);
+            assert_eq!(natural_cmp("Apple", "banana"), Ordering::Less);
+        }
+
+        #[test]
+        fn prefixes_come_first() {
+            assert_eq!(natural_cmp("abc", "abcd"), Ordering::Less);
+            assert_eq!(natural_cmp("", "a"), Ordering::Less);
+        }
+
+        #[test]
+        fn ties_fall_back_to_bytes() {
+            // Leading zeros and case only decide when nothing else does
+            assert_eq!(natural_cmp("x01", "x1"), Ordering::Less);
+            assert_eq!(natural_cmp("A", "a"), Ordering::Less);
+            assert_eq!(natural_cmp("same", "same"), Ordering::Equal);
+        }
+
+        #[test]
+        fn sorts_a_listing() {
+            let mut names = vec!["img12.png", "img10.png", "IMG2.png", "img1.png"];
+            names.sort_by(|a, b| natural_cmp(a, b));
+            assert_eq!(names, ["img1.png", "IMG2.png", "img10.png", "img12.png"]);
         }
-        pstring
     }
 }
 
@@ -2467,8 +8047,9 @@ mod util {
 // NodeInfo holds information each node
 mod node_info {
     use std::fs;
-    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
     use std::path::Path;
+    use std::time::SystemTime;
 
     use mime_guess::mime;
 
@@ -2482,6 +8063,7 @@ mod node_info {
         Executable,   // An executable file
         Image,        // An image file
         Symlink,      // A symbolic link
+        Match,        // A content search match, name is "path:line: snippet"
         Unknown,      // Unknown, unsupported, etc.
     }
     impl NodeType {
@@ -2511,17 +8093,40 @@ mod node_info {
         }
     }
 
+    // Metadata read while listing, shown in the long listing
+    #[derive(Clone)]
+    pub struct NodeStat {
+        pub size: u64,
+        pub modified: Option<SystemTime>,
+        pub mode: u32,
+        pub uid: u32,
+        pub gid: u32,
+    }
+    impl NodeStat {
+        pub fn from_metadata(metadata: &fs::Metadata) -> Self {
+            Self {
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+                mode: metadata.mode(),
+                uid: metadata.uid(),
+                gid: metadata.gid(),
+            }
+        }
+    }
+
     // Information about a file or directory
     #[derive(Clone)]
     pub struct NodeInfo {
         pub name: String,
         pub node_type: NodeType,
+        pub stat: Option<NodeStat>, // Only for entries read from disk
     }
     impl NodeInfo {
         pub fn new() -> Self {
             Self {
                 name: String::new(),
                 node_type: NodeType::Unknown,
+                stat: None,
             }
         }
         // UNUSED
@@ -2556,13 +8161,15 @@ mod node_info {
         pub fn is_image(&self) -> bool {
             return self.node_type == NodeType::Image;
         }
+        pub fn is_match(&self) -> bool {
+            self.node_type == NodeType::Match
+        }
         pub fn is_unknown(&self) -> bool {
             return self.node_type == NodeType::Unknown;
         }
-        // Unused
-        // pub fn is_symlink(&self) -> bool {
-        //     return self.node_type == NodeType::Symlink;
-        // }
+        pub fn is_symlink(&self) -> bool {
+            self.node_type == NodeType::Symlink
+        }
     }
 }
 
@@ -2572,16 +8179,60 @@ enum LoopReturn {
     Ok,
 }
 
+// Directory state of a tab while another tab is active
+#[derive(Clone)]
+struct Tab {
+    cwd: PathBuf,
+    lwd: PathBuf,
+    search_buf: String,
+    focused_name: String,
+    focus_index: usize,
+    history: Vec<PathBuf>,
+    history_pos: usize,
+    mode_explode: bool,
+    mode_grep: bool,
+}
+
+impl Tab {
+    fn new(cwd: PathBuf) -> Self {
+        Self {
+            lwd: cwd.clone(),
+            history: vec![cwd.clone()],
+            cwd,
+            search_buf: String::new(),
+            focused_name: String::new(),
+            focus_index: 0,
+            history_pos: 0,
+            mode_explode: false,
+            mode_grep: false,
+        }
+    }
+}
+
 // Main application state and control methods
 struct App<'a> {
     async_queue: aq::Queue,
+    journal: journal::Journal,
+    frecency: frecency::Db,
+    bookmarks: bookmarks::BookmarkList,
+    conflicts: Vec<conflict::Pending>,
+    rename_plan: Vec<(PathBuf, PathBuf)>,
+    conflict_all: Option<conflict::Policy>, // Policy chosen with "apply to all"
+    prompt: Option<prompt::Prompt>,
     should_quit: bool,
     cursor: usize,
     search_buf: String,
-    listing: Vec<NodeInfo>, // Full listing data
-    results: Vec<NodeInfo>, // Filtered listing data
+    matcher: Option<Arc<matching::Matcher>>, // None if the search is not a valid regex or glob
+    matcher_key: Option<(String, matching::Mode, matching::Case)>, // What it was built from
+    match_hits: HashMap<String, Vec<usize>>, // Matched characters by name, for highlighting
+    listing: Vec<NodeInfo>,                  // Full listing data
+    results: Vec<NodeInfo>,                  // Filtered listing data
     focused: NodeInfo,
     focus_index: usize,
+    focus_target: Option<String>,
+    hex_flip: Option<PathBuf>, // File previewed as text/hex against its detected content
+    mode_grep: bool,           // Search file contents instead of names
+    grep_rx: Option<tokio::sync::mpsc::UnboundedReceiver<NodeInfo>>, // Streamed content matches
     multi_selection: Vec<PathBuf>,
     preview_content: Text<'a>,
     preview_image: Option<StatefulProtocol>,
@@ -2589,12 +8240,22 @@ struct App<'a> {
     scroll_off_output: u16,
     cwd: PathBuf,
     lwd: PathBuf,
+    history: Vec<PathBuf>, // Directories visited this session
+    history_pos: usize,    // Index of the cwd in history
+    dir_state: HashMap<PathBuf, (String, String)>, // Focused name and search when a dir was left
     mode_explode: bool,
     mode_cmd_finder: bool,
+    mode_jump: bool,      // Listing shows the most visited directories
+    mode_history: bool,   // Listing shows the directories visited this session
+    mode_bookmarks: bool, // Listing shows the bookmarks by name
+    tabs: Vec<Tab>,       // The active tab's entry is only current after switching away
+    tab_index: usize,
     show_command_window: bool,
     command_input: String,
     term_clear: bool, // When true the terminal will be cleared on next draw
     show_output_window: bool,
+    show_jobs_window: bool,
+    jobs_index: usize,
     output_title: String,
     output_text: String,
     cmd_list: cmd_data::CmdList,
@@ -2642,13 +8303,27 @@ impl<'a> App<'a> {
 
         Self {
             async_queue: aq::Queue::new(),
+            journal: journal::Journal::load(),
+            frecency: frecency::Db::load(),
+            bookmarks: bookmarks::load(),
+            conflicts: Vec::new(),
+            rename_plan: Vec::new(),
+            conflict_all: None,
+            prompt: None,
             should_quit: false,
             cursor: 0,
             search_buf: String::new(),
+            matcher: None,
+            matcher_key: None,
+            match_hits: HashMap::new(),
             listing: Vec::new(),
             results: Vec::new(),
             focused: NodeInfo::new(),
             focus_index: 0,
+            focus_target: None,
+            hex_flip: None,
+            mode_grep: false,
+            grep_rx: None,
             multi_selection: Vec::new(),
             preview_content: Default::default(),
             preview_image: None,
@@ -2656,12 +8331,22 @@ impl<'a> App<'a> {
             scroll_off_output: 0,
             cwd: env::current_dir().unwrap(),
             lwd: env::current_dir().unwrap(),
+            history: vec![env::current_dir().unwrap()],
+            history_pos: 0,
+            dir_state: HashMap::new(),
             mode_explode: false,
             mode_cmd_finder: false,
+            mode_jump: false,
+            mode_history: false,
+            mode_bookmarks: false,
+            tabs: vec![Tab::new(env::current_dir().unwrap())],
+            tab_index: 0,
             show_command_window: false,
             command_input: String::new(),
             term_clear: true, // Always clear on start
             show_output_window: false,
+            show_jobs_window: false,
+            jobs_index: 0,
             output_title: String::new(),
             output_text: String::new(),
             cmd_list: cmd_data::make_cmd_list(),
@@ -2703,8 +8388,117 @@ impl<'a> App<'a> {
                 temp_path
             }
         };
+        self.change_cwd(new_path, true);
+    }
+
+    // Reload the listing in the new order keeping the focused item
+    fn resort(&mut self) {
+        self.focus_target = Some(self.focused.name.clone());
+        self.update_listing();
+        self.update_results();
+    }
+
+    // Store the active tab and restore the one at index
+    fn switch_tab(&mut self, index: usize) {
+        self.tabs[self.tab_index] = Tab {
+            cwd: self.cwd.clone(),
+            lwd: self.lwd.clone(),
+            search_buf: self.search_buf.clone(),
+            focused_name: self.focused.name.clone(),
+            focus_index: self.focus_index,
+            history: self.history.clone(),
+            history_pos: self.history_pos,
+            mode_explode: self.mode_explode,
+            mode_grep: self.mode_grep,
+        };
+        self.load_tab(index);
+    }
+
+    // Make the tab at index active without storing the current one
+    fn load_tab(&mut self, index: usize) {
+        let tab = self.tabs[index].clone();
+        self.tab_index = index;
+        self.cwd = tab.cwd;
+        self.lwd = tab.lwd;
+        self.search_buf = tab.search_buf;
+        self.history = tab.history;
+        self.history_pos = tab.history_pos;
+        self.mode_explode = tab.mode_explode;
+        self.mode_grep = tab.mode_grep;
+        self.mode_cmd_finder = false;
+        self.mode_jump = false;
+        self.mode_history = false;
+        self.mode_bookmarks = false;
+        self.grep_rx = None;
+        self.focus_target = Some(tab.focused_name);
+        self.update_listing();
+        self.update_results();
+        self.focus_index = tab.focus_index;
+    }
+
+    // Remember the focused item and search of the cwd for when we come back
+    fn remember_dir(&mut self) {
+        // Names in the picker modes aren't part of the cwd
+        if self.mode_jump || self.mode_history || self.mode_bookmarks || self.mode_cmd_finder {
+            return;
+        }
+        self.dir_state.insert(
+            self.cwd.clone(),
+            (self.focused.name.clone(), self.search_buf.clone()),
+        );
+    }
+
+    // Switch to a resolved path, optionally adding it to the session history
+    fn change_cwd(&mut self, new_path: PathBuf, record: bool) {
+        // Entering clears the search first, keep what it remembered
+        let just_remembered = self.search_buf.is_empty()
+            && self
+                .dir_state
+                .get(&self.cwd)
+                .is_some_and(|(focused, _)| *focused == self.focused.name);
+        if !just_remembered {
+            self.remember_dir();
+        }
         self.lwd = self.cwd.clone();
         self.cwd = new_path;
+        self.focus_target = None;
+        if record && self.history.get(self.history_pos) != Some(&self.cwd) {
+            self.history.truncate(self.history_pos + 1);
+            self.history.push(self.cwd.clone());
+            if self.history.len() > MAX_HISTORY {
+                self.history.remove(0);
+            }
+            self.history_pos = self.history.len() - 1;
+        }
+        // Put the cursor back where it was, or on the dir we came up from
+        if let Some((focused, search)) = self.dir_state.get(&self.cwd) {
+            self.focus_target = Some(focused.clone());
+            self.search_buf = search.clone();
+        } else if let Ok(rest) = self.lwd.strip_prefix(&self.cwd) {
+            self.focus_target = rest
+                .components()
+                .next()
+                .map(|first| first.as_os_str().to_string_lossy().to_string());
+        }
+        // Only real directories are remembered for jump
+        if let Some(visited) = fs::canonicalize(&self.cwd).ok().filter(|p| p.is_dir()) {
+            self.frecency.visit(&visited);
+        }
+    }
+
+    // Reload and focus a new entry, nested paths focus their top level entry
+    fn focus_created(&mut self, rel: &Path) {
+        let target = if self.mode_explode {
+            rel.to_string_lossy().to_string()
+        } else {
+            match rel.components().next() {
+                Some(first) => first.as_os_str().to_string_lossy().to_string(),
+                None => return,
+            }
+        };
+        self.focus_target = Some(target);
+        self.update_listing();
+        self.update_results();
     }
 
     // If no focused, use cwd
@@ -2717,6 +8511,19 @@ impl<'a> App<'a> {
             return focused_path;
         }
 
+        // Bookmarks point at their target
+        let target =
+            bookmarks::find(&self.bookmarks, &self.focused.name).filter(|_| self.mode_bookmarks);
+        if let Some(target) = target {
+            return target.clone();
+        }
+
+        // Content search matches point at their file
+        let location = grep::parse(&self.focused.name).filter(|_| self.focused.is_match());
+        if let Some((path, _)) = location {
+            return self.cwd.join(path);
+        }
+
         // If no multi selection, use focused
         focused_path.push(&self.focused.name);
         // If focused is not a path, use cwd
@@ -2735,10 +8542,60 @@ impl<'a> App<'a> {
         Box::pin(async move {
             let mut entries = Vec::new();
 
+            // Archives are listed like directories
+            if let Some((archive_path, inner)) = archive::split(&path) {
+                let owned_path = path.clone();
+                let members =
+                    tokio::task::spawn_blocking(move || archive::entries(&archive_path)).await;
+                let meta = node_meta::NodeMeta::get(&path);
+                let members = match members {
+                    Ok(Ok(members)) => members,
+                    _ => {
+                        log!("Failed to read archive: {}", path.to_str().unwrap());
+                        return aq::ResData::as_listing(1, entries, meta);
+                    }
+                };
+                for member in members {
+                    let name = member
+                        .path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    if !show_hidden && name.starts_with('.') {
+                        continue;
+                    }
+                    let node_type = if member.is_dir {
+                        NodeType::Directory
+                    } else {
+                        NodeType::File
+                    };
+                    if mode_explode {
+                        if !member.is_dir && member.path.starts_with(&inner) {
+                            let full = owned_path.join(member.path.strip_prefix(&inner).unwrap());
+                            entries.push(NodeInfo {
+                                name: full.to_string_lossy().to_string(),
+                                node_type,
+                                stat: None,
+                            });
+                        }
+                    } else if member.path.parent() == Some(inner.as_path()) {
+                        entries.push(NodeInfo {
+                            name,
+                            node_type,
+                            stat: None,
+                        });
+                    }
+                }
+                return aq::ResData::as_listing(0, entries, meta);
+            }
+
             match tokio::fs::read_dir(path.clone()).await {
                 Ok(mut read_dir) => {
+                    // Hidden entries count too, for the metadata panel
+                    let mut items = 0;
                     while let Some(entry_result) = read_dir.next_entry().await.transpose() {
                         if let Ok(entry) = entry_result {
+                            items += 1;
                             let file_name = entry.file_name();
                             let file_name_str = file_name.to_string_lossy();
                             match entry.metadata().await {
@@ -2766,6 +8623,7 @@ impl<'a> App<'a> {
                                             entries.push(NodeInfo {
                                                 name: sub_path.to_str().unwrap().to_string(),
                                                 node_type,
+                                                stat: Some(NodeStat::from_metadata(&metadata)),
                                             });
                                         }
                                     } else {
@@ -2775,6 +8633,7 @@ impl<'a> App<'a> {
                                         entries.push(NodeInfo {
                                             name: file_name_str.to_string(),
                                             node_type,
+                                            stat: Some(NodeStat::from_metadata(&metadata)),
                                         });
                                     }
                                 }
@@ -2785,7 +8644,10 @@ impl<'a> App<'a> {
                             }
                         }
                     }
-                    let meta = node_meta::NodeMeta::get(&path);
+                    let meta = node_meta::NodeMeta {
+                        items: Some(items),
+                        ..node_meta::NodeMeta::get(&path)
+                    };
                     return aq::ResData::as_listing(0, entries.clone(), meta);
                 }
                 Err(_) => {
@@ -2964,7 +8826,10 @@ impl<'a> App<'a> {
         self.preview_content += Line::styled("Your keybinds:", Style::default().fg(self.cs.header));
         for kb in self.keybinds.iter() {
             let kb_short = kb::to_string_short(&kb);
-            let cmd_name = cmd_data::get_cmd(&self.cmd_list, &kb.command);
+            let mut cmd_name = cmd_data::get_cmd(&self.cmd_list, &kb.command);
+            for arg in kb.args.iter() {
+                cmd_name += &format!(" {}", arg);
+            }
             let kb_span = Span::styled(
                 format!("{:<16}", cmd_name),
                 Style::default().fg(self.cs.command),
@@ -2976,8 +8841,21 @@ impl<'a> App<'a> {
         self.preview_content += Line::from("");
     }
 
-    fn pretty_dir_list(&self, list: &Vec<NodeInfo>) -> Text<'a> {
+    // Characters which matched the search are highlighted when hits are given
+    fn pretty_dir_list(
+        &self,
+        list: &[NodeInfo],
+        hits: Option<&HashMap<String, Vec<usize>>>,
+    ) -> Text<'a> {
         let mut text = Text::default();
+        // Owners are padded to the longest one shown
+        let owner_width = list
+            .iter()
+            .take(self.cfg.list_limit as usize)
+            .filter_map(|item| item.stat.as_ref())
+            .map(|stat| util::user_name(stat.uid).len() + util::group_name(stat.gid).len() + 1)
+            .max()
+            .unwrap_or(0);
         for item in list.iter().take(self.cfg.list_limit as usize) {
             // Check if this item is part of the multi selection
             let mut ms = "";
@@ -2994,36 +8872,29 @@ impl<'a> App<'a> {
             if is_multi_selected {
                 ms = &ms_on;
             }
-            let line = if item.is_shortcut() {
-                Line::styled(
-                    format!("{}{}| {}", ms, nf::CMD, item.name),
-                    Style::default().fg(self.cs.shortcut),
-                )
+            let (icon, name, color) = if item.is_shortcut() {
+                (nf::CMD, item.name.clone(), self.cs.shortcut)
             } else if item.is_dir() {
-                Line::styled(
-                    format!("{}{}| {}/", ms, nf::DIR, item.name),
-                    Style::default().fg(self.cs.dir),
-                )
+                (nf::DIR, format!("{}/", item.name), self.cs.dir)
             } else if item.is_command() {
-                Line::styled(
-                    format!("{}{}| {}", ms, nf::CMD, item.name),
-                    Style::default().fg(self.cs.command),
-                )
+                (nf::CMD, item.name.clone(), self.cs.command)
             } else if item.is_shell_command() {
-                Line::styled(
-                    format!("{}{}| {}", ms, nf::SCMD, item.name),
-                    Style::default().fg(self.cs.executable),
-                )
+                (nf::SCMD, item.name.clone(), self.cs.executable)
             } else if item.is_executable() {
-                Line::styled(
-                    format!("{}{}| {}", ms, nf::CMD, item.name),
-                    Style::default().fg(self.cs.executable),
-                )
+                (nf::CMD, item.name.clone(), self.cs.executable)
             } else if item.is_image() {
-                Line::styled(
-                    format!("{}{}| {}", ms, nf::IMG, item.name),
-                    Style::default().fg(self.cs.image),
-                )
+                (nf::IMG, item.name.clone(), self.cs.image)
+            } else if item.is_match() {
+                // Split "path:line: snippet" to color each part
+                let (location, snippet) = item.name.split_once(": ").unwrap_or((&item.name, ""));
+                text.lines.push(Line::from(vec![
+                    Span::styled(
+                        format!("{}{}| {}: ", ms, nf::MAG, location),
+                        Style::default().fg(self.cs.file),
+                    ),
+                    Span::styled(snippet.to_string(), Style::default().fg(self.cs.misc)),
+                ]));
+                continue;
             } else {
                 // When exploded the item name is the full path
                 // Remove the cwd prefix for better readability
@@ -3032,16 +8903,86 @@ impl<'a> App<'a> {
                 } else {
                     item.name.clone()
                 };
-                Line::styled(
-                    format!("{}{}| {}", ms, nf::FILE, name),
-                    Style::default().fg(self.cs.file),
-                )
+                (nf::FILE, name, self.cs.file)
+            };
+            let mut line = Line::styled(format!("{}{}| ", ms, icon), Style::default().fg(color));
+            if self.cfg.long_listing {
+                let columns = self.long_columns(item, owner_width);
+                line.spans
+                    .insert(0, Span::styled(columns, Style::default().fg(self.cs.dim)));
+            }
+            // Names are matched relative to the cwd, find that part in the shown name
+            let rel = matching::relative(&item.name, &self.cwd);
+            let hits = match (hits.and_then(|hits| hits.get(rel)), name.find(rel)) {
+                (Some(hits), Some(at)) => {
+                    let offset = name[..at].chars().count();
+                    hits.iter().map(|i| i + offset).collect()
+                }
+                _ => vec![],
             };
-            text.lines.push(Line::from(line));
+            // Group runs of matched and unmatched characters into spans
+            let mut run = String::new();
+            let mut run_hit = false;
+            for (i, c) in name.chars().enumerate() {
+                let hit = hits.contains(&i);
+                if hit != run_hit && !run.is_empty() {
+                    line.push_span(self.match_span(std::mem::take(&mut run), run_hit));
+                }
+                run_hit = hit;
+                run.push(c);
+            }
+            line.push_span(self.match_span(run, run_hit));
+            text.lines.push(line);
         }
         text
     }
 
+    // Detail columns of a listing entry, blank for entries not read from disk
+    fn long_columns(&self, item: &NodeInfo, owner_width: usize) -> String {
+        let mut out = String::new();
+        for column in self.cfg.long_columns.iter() {
+            let value = match (column, &item.stat) {
+                (cfg::Column::Size, Some(stat)) if !item.is_dir() => {
+                    format!("{:>10}", util::human_size(stat.size))
+                }
+                (cfg::Column::Size, _) => format!("{:>10}", ""),
+                (cfg::Column::Modified, Some(stat)) => format!(
+                    "{:>8}",
+                    stat.modified.map(util::relative_time).unwrap_or_default()
+                ),
+                (cfg::Column::Modified, None) => format!("{:>8}", ""),
+                (cfg::Column::Perms, Some(stat)) => util::perm_string(stat.mode),
+                (cfg::Column::Perms, None) => format!("{:9}", ""),
+                (cfg::Column::Owner, Some(stat)) => format!(
+                    "{:<width$}",
+                    format!(
+                        "{}:{}",
+                        util::user_name(stat.uid),
+                        util::group_name(stat.gid)
+                    ),
+                    width = owner_width
+                ),
+                (cfg::Column::Owner, None) => format!("{:width$}", "", width = owner_width),
+            };
+            out += &value;
+            out += "  ";
+        }
+        out
+    }
+
+    fn match_span(&self, text: String, hit: bool) -> Span<'a> {
+        if hit {
+            Span::styled(
+                text,
+                Style::default()
+                    .fg(self.cs.matched)
+                    .add_modifier(Modifier::BOLD),
+            )
+        } else {
+            Span::raw(text)
+        }
+    }
+
     fn pretty_metadata(&self, metadata: &node_meta::NodeMeta) -> Text<'a> {
         fn line(icon: &str, label: &str, value: &str, color: Color) -> Line<'static> {
             Line::styled(
@@ -3049,30 +8990,82 @@ impl<'a> App<'a> {
                 Style::default().fg(color),
             )
         }
+        // Local time followed by how long ago it was
+        fn date(time: SystemTime) -> String {
+            format!(
+                "{} ({})",
+                chrono::DateTime::<chrono::Local>::from(time).format("%Y-%m-%d %H:%M:%S"),
+                util::relative_time(time)
+            )
+        }
         let mut text = Text::default();
         text += Line::styled(
             format!("{} {}", nf::DIRO, metadata.path.to_str().unwrap()),
             Style::default().fg(self.cs.dir),
         );
+        text += line(nf::INFO, "mime", &metadata.mime, self.cs.info);
+        // Entries which aren't on disk (like archive members) have no details
+        if metadata.nlink == 0 {
+            return text;
+        }
+        if let Some(target) = &metadata.link_target {
+            text += line(
+                nf::RIGHT,
+                "link target",
+                &target.to_string_lossy(),
+                self.cs.info,
+            );
+        }
         text += line(
             nf::INFO,
             "permissions",
-            &format!("{}", metadata.permissions),
+            &format!(
+                "{:o} ({})",
+                metadata.permissions & 0o7777,
+                util::perm_string(metadata.permissions)
+            ),
             self.cs.info,
         );
         text += line(
             nf::INFO,
-            "size",
-            &format!("{}", metadata.size),
+            "owner",
+            &format!(
+                "{}:{}",
+                util::user_name(metadata.uid),
+                util::group_name(metadata.gid)
+            ),
             self.cs.info,
         );
+        match metadata.items {
+            Some(items) => text += line(nf::INFO, "items", &items.to_string(), self.cs.info),
+            None => {
+                text += line(
+                    nf::INFO,
+                    "size",
+                    &format!(
+                        "{} ({} bytes)",
+                        util::human_size(metadata.size),
+                        metadata.size
+                    ),
+                    self.cs.info,
+                )
+            }
+        }
+        for (label, time) in [
+            ("modified", metadata.modified),
+            ("accessed", metadata.accessed),
+            ("created", metadata.created),
+        ] {
+            if let Some(time) = time {
+                text += line(nf::INFO, label, &date(time), self.cs.info);
+            }
+        }
         text += line(
             nf::INFO,
-            "modified",
-            &format!("{}", metadata.modified),
+            "inode",
+            &format!("{} ({} links)", metadata.inode, metadata.nlink),
             self.cs.info,
         );
-        text += line(nf::INFO, "mime", &metadata.mime, self.cs.info);
         text
     }
 
@@ -3087,11 +9080,19 @@ impl<'a> App<'a> {
     }
 
     fn preview_file(&mut self, focused_path: &PathBuf) {
-        let focused_path = focused_path.clone();
+        self.preview_file_at(focused_path, None);
+    }
+
+    // Preview a file, starting a few lines above the given line and marking it
+    fn preview_file_at(&mut self, focused_path: &Path, at_line: Option<usize>) {
+        let focused_path = focused_path.to_path_buf();
         let cs = self.cs.clone();
         let preview_limit = self.cfg.preview_limit;
         let has_bat = self.has_bat;
         let sep = SEP.to_string();
+        let hex_flip = self.hex_flip.as_ref() == Some(&focused_path);
+        // First line shown, 1 based
+        let start = at_line.map_or(1, |line| line.saturating_sub(MATCH_CONTEXT).max(1));
 
         self.async_queue
             .add_task_unique(aq::Kind::FilePreview, async move {
@@ -3126,17 +9127,94 @@ impl<'a> App<'a> {
                     Color::Rgb(s.foreground.r, s.foreground.g, s.foreground.b)
                 }
 
+                // "00000010  48 65 6c 6c 6f 20 77 6f  72 6c 64 0a 00 00 00 00  |Hello world.....|"
+                fn hex_line(offset: usize, chunk: &[u8], cs: &cs::Colors) -> Line<'static> {
+                    let color = |b: u8| match b {
+                        0 => cs.dim,
+                        b if b.is_ascii_graphic() => cs.ok,
+                        b if b.is_ascii_whitespace() => cs.info,
+                        b if b.is_ascii() => cs.warning,
+                        _ => cs.misc,
+                    };
+                    let mut line = Line::default();
+                    line.push_span(Span::styled(
+                        format!("{:08x}  ", offset),
+                        Style::default().fg(cs.dim),
+                    ));
+                    for i in 0..16 {
+                        line.push_span(match chunk.get(i) {
+                            Some(b) => {
+                                Span::styled(format!("{:02x} ", b), Style::default().fg(color(*b)))
+                            }
+                            None => Span::raw("   "),
+                        });
+                        if i == 7 {
+                            line.push_span(Span::raw(" "));
+                        }
+                    }
+                    line.push_span(Span::styled(" |", Style::default().fg(cs.dim)));
+                    for b in chunk {
+                        let c = if b.is_ascii_graphic() || *b == b' ' {
+                            *b as char
+                        } else {
+                            '.'
+                        };
+                        line.push_span(Span::styled(c.to_string(), Style::default().fg(color(*b))));
+                    }
+                    line.push_span(Span::styled("|", Style::default().fg(cs.dim)));
+                    line
+                }
+
                 let mut text = Text::default();
                 let meta = crate::node_meta::NodeMeta::get(&focused_path);
+                // Files inside an archive are read from the archive
+                let member = crate::archive::split(&focused_path)
+                    .filter(|(_, inner)| !inner.as_os_str().is_empty());
 
                 text += Line::styled(sep.clone(), Style::default().fg(cs.dim));
 
+                // Binary files get a hex dump, the toggle swaps text and hex
+                let dump_len = preview_limit.saturating_mul(16).min(HEX_PREVIEW_MAX);
+                let head = match &member {
+                    Some((archive, inner)) => {
+                        crate::archive::read_member(archive, inner, MEMBER_PREVIEW_BYTES)
+                    }
+                    None => File::open(&focused_path).and_then(|file| {
+                        let mut buf = Vec::new();
+                        file.take(dump_len.max(BINARY_SNIFF_BYTES) as u64)
+                            .read_to_end(&mut buf)
+                            .map(|_| buf)
+                    }),
+                }
+                .unwrap_or_default();
+                let sniff = &head[..head.len().min(BINARY_SNIFF_BYTES)];
+                let binary = util::is_binary(sniff);
+                if binary != hex_flip {
+                    let label = if binary {
+                        "detected: binary"
+                    } else {
+                        "detected: text, shown as hex"
+                    };
+                    text += Line::styled(label, Style::default().fg(cs.info));
+                    text += Line::styled(sep, Style::default().fg(cs.dim));
+                    let shown = &head[..head.len().min(dump_len)];
+                    for (i, chunk) in shown.chunks(16).enumerate() {
+                        text += hex_line(i * 16, chunk, &cs);
+                    }
+                    return aq::ResData::as_file(0, text, meta);
+                }
+
                 // Try bat first
-                if has_bat {
+                if has_bat && member.is_none() {
                     if let Ok(bat_output) = std::process::Command::new("bat")
                         .arg("--color=always")
                         .arg("--style=plain")
-                        .arg(format!("--line-range=:{}", preview_limit))
+                        .arg(format!(
+                            "--line-range={}:{}",
+                            start,
+                            start.saturating_add(preview_limit - 1)
+                        ))
+                        .arg(format!("--highlight-line={}", at_line.unwrap_or(0)))
                         .arg(focused_path.to_str().unwrap())
                         .output()
                     {
@@ -3178,21 +9256,31 @@ impl<'a> App<'a> {
                 );
                 text += Line::styled(sep, Style::default().fg(cs.dim));
 
-                let file = File::open(&focused_path);
-                if let Ok(file) = file {
-                    let reader = BufReader::new(file);
+                let reader: std::io::Result<Box<dyn BufRead>> = match &member {
+                    Some(_) => Ok(Box::new(std::io::Cursor::new(head))),
+                    None => File::open(&focused_path)
+                        .map(|file| Box::new(BufReader::new(file)) as Box<dyn BufRead>),
+                };
+                if let Ok(reader) = reader {
                     for (i, line) in reader.lines().enumerate() {
-                        if i >= preview_limit {
+                        if i + 1 >= start.saturating_add(preview_limit) {
                             break;
                         }
                         if let Ok(line) = line {
+                            // Earlier lines are still highlighted to keep the syntax state
                             let ranges = h.highlight_line(&line, &ss).unwrap_or_default();
+                            if i + 1 < start {
+                                continue;
+                            }
                             let mut styled_line = Line::default();
                             for (style, text_part) in ranges {
-                                styled_line.push_span(Span::styled(
-                                    text_part.to_string(),
-                                    Style::default().fg(syntect_to_ratatui_color(style)),
-                                ));
+                                let mut span_style =
+                                    Style::default().fg(syntect_to_ratatui_color(style));
+                                if at_line == Some(i + 1) {
+                                    span_style = span_style.bg(cs.dim);
+                                }
+                                styled_line
+                                    .push_span(Span::styled(text_part.to_string(), span_style));
                             }
                             text += styled_line;
                         }
@@ -3208,6 +9296,108 @@ impl<'a> App<'a> {
             });
     }
 
+    // Member list and totals of an archive
+    fn preview_archive(&mut self, focused_path: &Path) {
+        let focused_path = focused_path.to_path_buf();
+        let cs = self.cs.clone();
+        let preview_limit = self.cfg.preview_limit;
+        let sep = SEP.to_string();
+
+        self.async_queue
+            .add_task_unique(aq::Kind::FilePreview, async move {
+                let mut text = Text::default();
+                let meta = node_meta::NodeMeta::get(&focused_path);
+                text += Line::styled(sep.clone(), Style::default().fg(cs.dim));
+
+                let owned_path = focused_path.clone();
+                let entries = match tokio::task::spawn_blocking(move || {
+                    archive::entries(&owned_path)
+                })
+                .await
+                {
+                    Ok(Ok(entries)) => entries,
+                    Ok(Err(e)) => {
+                        text += Line::styled(
+                            format!("Err: Unable to read archive: {}", e),
+                            Style::default().fg(cs.error),
+                        );
+                        return aq::ResData::as_file(1, text, meta);
+                    }
+                    Err(e) => {
+                        text += Line::styled(
+                            format!("Err: Unable to read archive: {}", e),
+                            Style::default().fg(cs.error),
+                        );
+                        return aq::ResData::as_file(1, text, meta);
+                    }
+                };
+
+                let files = entries.iter().filter(|entry| !entry.is_dir).count();
+                let total: u64 = entries.iter().map(|entry| entry.size).sum();
+                let ratio = if total > 0 {
+                    format!("{:.1}%", meta.size as f64 / total as f64 * 100.0)
+                } else {
+                    "-".to_string()
+                };
+                text += Line::styled(
+                    format!(
+                        "entries: {} ({} files, {} dirs)",
+                        entries.len(),
+                        files,
+                        entries.len() - files
+                    ),
+                    Style::default().fg(cs.info),
+                );
+                text += Line::styled(
+                    format!("unpacked: {}", util::human_size(total)),
+                    Style::default().fg(cs.info),
+                );
+                text += Line::styled(
+                    format!(
+                        "packed: {} ({} of unpacked)",
+                        util::human_size(meta.size),
+                        ratio
+                    ),
+                    Style::default().fg(cs.info),
+                );
+                text += Line::styled(sep, Style::default().fg(cs.dim));
+
+                // Leave room for the "more" line when the list is cut
+                let room = preview_limit.saturating_sub(text.lines.len());
+                let shown = if entries.len() > room {
+                    room.saturating_sub(1)
+                } else {
+                    entries.len()
+                };
+                for entry in entries.iter().take(shown) {
+                    let (size, name, color) = if entry.is_dir {
+                        (
+                            String::new(),
+                            format!("{}/", entry.path.to_string_lossy()),
+                            cs.dir,
+                        )
+                    } else {
+                        (
+                            util::human_size(entry.size),
+                            entry.path.to_string_lossy().to_string(),
+                            cs.file,
+                        )
+                    };
+                    text += Line::from(vec![
+                        Span::styled(format!("{:>10}  ", size), Style::default().fg(cs.dim)),
+                        Span::styled(name, Style::default().fg(color)),
+                    ]);
+                }
+                if shown < entries.len() {
+                    text += Line::styled(
+                        format!("... {} more", entries.len() - shown),
+                        Style::default().fg(cs.dim),
+                    );
+                }
+                aq::ResData::as_file(0, text, meta)
+            });
+    }
+
     fn preview_image(&mut self, focused_path: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
         let mut picker = Picker::from_fontsize((6, 12));
         if self.cfg.force_sixel {
@@ -3256,8 +9446,16 @@ impl<'a> App<'a> {
             }
             sc::DIR_BACK => {
                 self.preview_content = Text::default();
-                self.preview_content += self.fmtln_path(&self.lwd);
-                self.preview_content += self.fmtln_sc("Go back to the last working directory");
+                match self.history_pos.checked_sub(1) {
+                    Some(pos) => {
+                        self.preview_content += self.fmtln_path(&self.history[pos].clone());
+                        self.preview_content += self.fmtln_sc("Go back to the previous directory");
+                    }
+                    None => {
+                        self.preview_content +=
+                            self.fmtln_sc("No earlier directory in this session");
+                    }
+                }
             }
             sc::EXP => {
                 self.preview_content = Text::default();
@@ -3388,11 +9586,44 @@ impl<'a> App<'a> {
                     };
                     return;
                 }
+                // Bookmarks preview their target
+                if self.mode_bookmarks {
+                    match bookmarks::find(&self.bookmarks, &self.focused.name).cloned() {
+                        Some(target) if target.is_dir() => self.preview_dir(&target),
+                        Some(target) => {
+                            self.preview_content = Text::default();
+                            self.preview_content += self.fmtln_path(&target);
+                            self.preview_content += Line::styled(
+                                "This directory no longer exists.",
+                                Style::default().fg(self.cs.error),
+                            );
+                        }
+                        None => self.preview_content = Text::default(),
+                    }
+                    return;
+                }
                 // We have a file or dir
                 let mut focused_path = self.cwd.clone();
                 focused_path.push(&self.focused.name);
 
-                if self.focused.is_dir() {
+                let is_archive = archive::Format::from_path(&focused_path).is_some()
+                    && self.focused.is_file_like()
+                    && focused_path.is_file();
+                if is_archive {
+                    self.preview_archive(&focused_path);
+                } else if self.focused.is_match() {
+                    match grep::parse(&self.focused.name) {
+                        Some((path, line)) => {
+                            self.preview_file_at(&self.cwd.join(path), Some(line))
+                        }
+                        None => {
+                            self.preview_content = Text::styled(
+                                "Error: Unable to read the match location.",
+                                Style::default().fg(self.cs.error),
+                            );
+                        }
+                    }
+                } else if self.focused.is_dir() {
                     self.preview_dir(&focused_path);
                 } else if self.focused.is_file() {
                     self.preview_file(&focused_path);
@@ -3445,6 +9676,7 @@ impl<'a> App<'a> {
             NodeInfo {
                 name: sc::EXP.to_string(),
                 node_type: NodeType::Shortcut,
+                stat: None,
             },
         );
         self.listing.insert(
@@ -3452,6 +9684,7 @@ impl<'a> App<'a> {
             NodeInfo {
                 name: sc::EXIT.to_string(),
                 node_type: NodeType::Shortcut,
+                stat: None,
             },
         );
         self.listing.insert(
@@ -3459,6 +9692,7 @@ impl<'a> App<'a> {
             NodeInfo {
                 name: sc::LOADING.to_string(),
                 node_type: NodeType::Shortcut,
+                stat: None,
             },
         );
         // Handle cmd finder
@@ -3469,6 +9703,7 @@ impl<'a> App<'a> {
                 self.listing.push(NodeInfo {
                     name: shell_cmd.to_string(),
                     node_type: NodeType::ShellCommand,
+                    stat: None,
                 });
             }
             // Sort the commands alphabetically
@@ -3481,30 +9716,83 @@ impl<'a> App<'a> {
                 self.listing.push(NodeInfo {
                     name: cmd_data.cmd.to_string(),
                     node_type: NodeType::Command,
+                    stat: None,
                 });
             }
             return;
         }
+        // Handle jump mode
+        if self.mode_jump {
+            self.listing = self
+                .frecency
+                .sorted()
+                .into_iter()
+                .map(|entry| NodeInfo {
+                    name: entry.path.to_string_lossy().to_string(),
+                    node_type: NodeType::Directory,
+                    stat: None,
+                })
+                .collect();
+            return;
+        }
+        // Handle bookmark mode
+        if self.mode_bookmarks {
+            self.listing = self
+                .bookmarks
+                .iter()
+                .map(|(name, _)| NodeInfo {
+                    name: name.clone(),
+                    node_type: NodeType::Directory,
+                    stat: None,
+                })
+                .collect();
+            return;
+        }
+        // Handle history mode, most recent first
+        if self.mode_history {
+            self.listing.clear();
+            for path in self.history.iter().rev() {
+                let name = path.to_string_lossy().to_string();
+                if !self.listing.iter().any(|node| node.name == name) {
+                    self.listing.push(NodeInfo {
+                        name,
+                        node_type: NodeType::Directory,
+                        stat: None,
+                    });
+                }
+            }
+            return;
+        }
         // Normal directory listing
         let owned_cwd = self.cwd.clone();
         let owned_explode = self.mode_explode;
         let owned_hidden = self.cfg.show_hidden;
+        let owned_sort = self.cfg.sort;
         self.async_queue
             .add_task_unique(aq::Kind::ListingDir, async move {
                 let mut listing_res =
                     App::get_directory_listing(owned_cwd.clone(), owned_explode, owned_hidden)
                         .await;
                 // Turn listing into listing vec
-                let mut listing = match listing_res.data_listing.take() {
+                let listing = match listing_res.data_listing.take() {
                     Some(list) => list,
                     None => Vec::new(),
                 };
+                let sort_dir = owned_cwd.clone();
+                let mut listing = tokio::task::spawn_blocking(move || {
+                    let mut listing = listing;
+                    sort::apply(&mut listing, &sort_dir, owned_sort);
+                    listing
+                })
+                .await
+                .unwrap_or_default();
                 // Inserted in reverse order
                 listing.insert(
                     0,
                     NodeInfo {
                         name: sc::DIR_BACK.to_string(),
                         node_type: NodeType::Shortcut,
+                        stat: None,
                     },
                 );
                 listing.insert(
@@ -3512,6 +9800,7 @@ impl<'a> App<'a> {
                     NodeInfo {
                         name: sc::DIR_UP.to_string(),
                         node_type: NodeType::Shortcut,
+                        stat: None,
                     },
                 );
                 listing.insert(
@@ -3519,6 +9808,7 @@ impl<'a> App<'a> {
                     NodeInfo {
                         name: sc::CMDS.to_string(),
                         node_type: NodeType::Shortcut,
+                        stat: None,
                     },
                 );
                 listing.insert(
@@ -3526,6 +9816,7 @@ impl<'a> App<'a> {
                     NodeInfo {
                         name: sc::EXP.to_string(),
                         node_type: NodeType::Shortcut,
+                        stat: None,
                     },
                 );
                 listing.insert(
@@ -3533,6 +9824,7 @@ impl<'a> App<'a> {
                     NodeInfo {
                         name: sc::EXIT.to_string(),
                         node_type: NodeType::Shortcut,
+                        stat: None,
                     },
                 );
                 let meta = node_meta::NodeMeta::get(&owned_cwd);
@@ -3542,22 +9834,41 @@ impl<'a> App<'a> {
 
     // Fuzzy finding
     fn update_results(&mut self) {
+        if let Some(query) = self.grep_query() {
+            self.update_grep(query);
+            return;
+        }
+        // Stops any content search still running
+        self.grep_rx = None;
         let limit = self.cfg.find_limit;
-        let input = self.search_buf.clone();
+        let query = query::parse(&self.search_buf);
         let listing = self.listing.clone();
+        let cwd = self.cwd.clone();
+        // Jump targets keep their frecency order, the search only narrows them down
+        let by_score = !self.mode_jump;
+        self.update_matcher();
+        let matcher = self.matcher.clone();
         self.async_queue
             .add_task_unique(aq::Kind::ListingResult, async move {
-                let matcher = SkimMatcherV2::default();
+                // An invalid regex or glob matches nothing
+                let matcher = match matcher {
+                    Some(matcher) => matcher,
+                    None => {
+                        return aq::ResData::as_listing(0, vec![], node_meta::NodeMeta::empty());
+                    }
+                };
                 let mut scored: Vec<_> = listing
                     .iter()
                     .take(limit as usize) // Limit for performance
+                    .filter(|item| query.keep(item, &cwd))
                     .filter_map(|item| {
-                        matcher
-                            .fuzzy_match(&item.name, &input)
-                            .map(|score| (score, item.clone()))
+                        let name = matching::relative(&item.name, &cwd);
+                        matcher.score(name).map(|score| (score, item.clone()))
                     })
                     .collect();
-                scored.sort_by(|a, b| b.0.cmp(&a.0));
+                if by_score {
+                    scored.sort_by(|a, b| b.0.cmp(&a.0));
+                }
                 aq::ResData::as_listing(
                     0,
                     scored.into_iter().map(|(_, item)| item).collect(),
@@ -3566,6 +9877,62 @@ impl<'a> App<'a> {
             });
     }
 
+    // Rebuild the search matcher, only if the terms, mode or case changed
+    fn update_matcher(&mut self) {
+        let key = (
+            query::parse(&self.search_buf).terms,
+            self.cfg.match_mode,
+            self.cfg.match_case,
+        );
+        if self.matcher_key.as_ref() == Some(&key) {
+            return;
+        }
+        self.matcher = matching::Matcher::new(key.1, key.2, &key.0)
+            .ok()
+            .map(Arc::new);
+        self.matcher_key = Some(key);
+        self.match_hits.clear();
+    }
+
+    // The content search query when searching file contents
+    // Either the mode is on or the search starts with the prefix
+    fn grep_query(&self) -> Option<String> {
+        if self.mode_cmd_finder || self.mode_jump || self.mode_history || self.mode_bookmarks {
+            return None;
+        }
+        if self.mode_grep {
+            return Some(self.search_buf.clone());
+        }
+        self.search_buf
+            .strip_prefix(grep::PREFIX)
+            .map(|query| query.to_string())
+    }
+
+    // Search the contents of files under cwd, matches are streamed into the results
+    fn update_grep(&mut self, query: String) {
+        self.results.clear();
+        self.focus_index = 0;
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        self.grep_rx = Some(rx);
+        if query.is_empty() {
+            self.update_focused();
+            self.update_preview();
+            return;
+        }
+        let root = self.cwd.clone();
+        let recursive = self.mode_explode;
+        let show_hidden = self.cfg.show_hidden;
+        self.async_queue
+            .add_task_unique(aq::Kind::ContentSearch, async move {
+                let count = tokio::task::spawn_blocking(move || {
+                    grep::search(&root, &query, recursive, show_hidden, &tx)
+                })
+                .await
+                .unwrap_or(0);
+                aq::ResData::as_str(0, format!("{} matches", count))
+            });
+    }
+
     fn reset_sec_scroll(&mut self) {
         self.scroll_off_preview = 0;
         self.scroll_off_output = 0;
@@ -3590,6 +9957,23 @@ impl<'a> App<'a> {
         return old.name != self.focused.name;
     }
 
+    fn input_prompt(&mut self, modifiers: KeyModifiers, code: KeyCode) {
+        let prompt = match &self.prompt {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        let answer = match (modifiers, code) {
+            (_, KeyCode::Esc) => None,
+            (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char(c)) if prompt.accepts(c) => {
+                Some(c)
+            }
+            _ => return,
+        };
+        let on_answer = prompt.on_answer;
+        self.prompt = None;
+        on_answer(self, answer);
+    }
+
     fn input_out_window(&mut self, modifiers: KeyModifiers, code: KeyCode) {
         match (modifiers, code) {
             (KeyModifiers::NONE, KeyCode::Esc) => {
@@ -3617,6 +10001,62 @@ impl<'a> App<'a> {
         self.handle_cmd(&cmd.to_string());
     }
 
+    fn input_jobs_window(&mut self, modifiers: KeyModifiers, code: KeyCode) {
+        // Rows are pending jobs followed by finished jobs, newest first
+        let pending = self
+            .async_queue
+            .get_pending()
+            .iter()
+            .map(|item| item.id)
+            .collect::<Vec<usize>>();
+        let finished = self
+            .async_queue
+            .get_finished()
+            .iter()
+            .rev()
+            .map(|job| job.id)
+            .collect::<Vec<usize>>();
+        let rows = pending.len() + finished.len();
+        self.jobs_index = self.jobs_index.min(rows.saturating_sub(1));
+        let selected = pending
+            .iter()
+            .chain(finished.iter())
+            .nth(self.jobs_index)
+            .copied();
+        let kb_up = kb::find_by_cmd(&self.keybinds, &cmd_data::CmdName::CurUp).unwrap();
+        let kb_dn = kb::find_by_cmd(&self.keybinds, &cmd_data::CmdName::CurDown).unwrap();
+        match (modifiers, code) {
+            (KeyModifiers::NONE, KeyCode::Esc | KeyCode::Enter) => {
+                self.show_jobs_window = false;
+            }
+            v if v == (kb_up.modifiers, kb_up.code) || v == (KeyModifiers::NONE, KeyCode::Up) => {
+                self.jobs_index = self.jobs_index.saturating_sub(1);
+            }
+            v if v == (kb_dn.modifiers, kb_dn.code) || v == (KeyModifiers::NONE, KeyCode::Down) => {
+                self.jobs_index = (self.jobs_index + 1).min(rows.saturating_sub(1));
+            }
+            (KeyModifiers::NONE, KeyCode::Char('c')) => {
+                if let Some(id) = selected {
+                    self.async_queue.cancel(id);
+                }
+            }
+            (KeyModifiers::NONE, KeyCode::Char('p')) => {
+                if let Some(id) = selected {
+                    self.async_queue.toggle_pause(id);
+                }
+            }
+            (KeyModifiers::NONE, KeyCode::Char('r')) => {
+                if let Some(requeue) = selected.and_then(|id| self.async_queue.retry(id)) {
+                    requeue(self);
+                }
+            }
+            (KeyModifiers::NONE, KeyCode::Char('x')) => {
+                self.async_queue.clear_finished();
+            }
+            _ => {}
+        }
+    }
+
     fn input_cmd_window(&mut self, modifiers: KeyModifiers, code: KeyCode) -> LoopReturn {
         match (modifiers, code) {
             (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char(c)) => {
@@ -3647,12 +10087,103 @@ impl<'a> App<'a> {
             }
             (KeyModifiers::NONE, KeyCode::Esc) => {
                 self.show_command_window = false;
+                // Drop any live preview
+                self.update_preview();
+                return LoopReturn::Ok;
             }
             _ => {}
         }
+        self.update_cmd_preview();
         LoopReturn::Ok
     }
 
+    // Live preview in the preview pane while typing some commands
+    fn update_cmd_preview(&mut self) {
+        let args = match self
+            .command_input
+            .trim_start()
+            .strip_prefix("rename-pattern ")
+        {
+            Some(args) => args
+                .split_whitespace()
+                .map(|arg| arg.to_string())
+                .collect::<Vec<String>>(),
+            None => return,
+        };
+        self.reset_sec_scroll();
+        self.preview_image = None;
+        let mut text = Text::default();
+        text += Line::styled(
+            "Rename preview: rename-pattern <from> <to>",
+            Style::default().fg(self.cs.header),
+        );
+        text += Line::styled(
+            "{n} {n:3} {stem} {ext} {1} $1 ${1}, transforms like {stem:upper} {1:lower} {ext:title}, {{ for {",
+            Style::default().fg(self.cs.tip),
+        );
+        text += Line::styled(SEP, Style::default().fg(self.cs.dim));
+        if args.is_empty() {
+            self.preview_content = text;
+            return;
+        }
+        let paths = self.rename_pattern_targets();
+        let to = if args.len() > 1 {
+            args[1..].join(" ")
+        } else {
+            "$0".to_string()
+        };
+        match rename::pattern_pairs(&paths, &args[0], &to) {
+            Ok(pairs) => {
+                // Count targets to flag collisions
+                let mut counts = std::collections::HashMap::new();
+                for (_, new) in pairs.iter() {
+                    *counts.entry(new.clone()).or_insert(0) += 1;
+                }
+                let sources = pairs
+                    .iter()
+                    .map(|(old, _)| old.clone())
+                    .collect::<Vec<PathBuf>>();
+                if pairs.is_empty() {
+                    text += Line::styled("No names match.", Style::default().fg(self.cs.dim));
+                }
+                for (old, new) in pairs.iter().take(self.cfg.preview_limit) {
+                    let collides = counts[new] > 1
+                        || (old != new && new.symlink_metadata().is_ok() && !sources.contains(new));
+                    let color = if collides {
+                        self.cs.error
+                    } else if old == new {
+                        self.cs.dim
+                    } else {
+                        self.cs.file
+                    };
+                    text += Line::styled(
+                        format!(
+                            "{} {} {}",
+                            old.file_name().unwrap_or_default().to_string_lossy(),
+                            nf::RIGHT,
+                            new.file_name().unwrap_or_default().to_string_lossy()
+                        ),
+                        Style::default().fg(color),
+                    );
+                }
+            }
+            Err(e) => text += Line::styled(e, Style::default().fg(self.cs.error)),
+        }
+        self.preview_content = text;
+    }
+
+    // The selection, or every item in the current results
+    fn rename_pattern_targets(&self) -> Vec<PathBuf> {
+        if !self.multi_selection.is_empty() {
+            return self.multi_selection.clone();
+        }
+        self.results
+            .iter()
+            .filter(|node| node.is_file_like())
+            .map(|node| self.cwd.join(&node.name))
+            .collect()
+    }
+
     // Returns true if input changed
     fn input_search(&mut self, modifiers: KeyModifiers, code: KeyCode) -> bool {
         match (modifiers, code) {
@@ -3674,8 +10205,11 @@ impl<'a> App<'a> {
         for kb in self.keybinds.iter() {
             if kb.modifiers == modifiers && kb.code == code {
                 cmd = self.get_cmd(&kb.command).to_string();
+                for arg in kb.args.iter() {
+                    cmd += &format!(" {}", arg);
+                }
                 let cmd_data = cmd_data::get_cmd_data(&self.cmd_list, &kb.command);
-                if cmd_data.params.len() > 0 {
+                if cmd_data.params.len() > kb.args.len() {
                     cmd += &format!(" {}", ASK); // Indicate that params are needed and should be asked for
                 }
                 break;
@@ -3731,9 +10265,21 @@ impl<'a> App<'a> {
                 aq::Kind::ListingPreview => self.loading_preview = true,
                 aq::Kind::FilePreview => self.loading_preview = true,
                 aq::Kind::ImagePreview => self.loading_preview = true,
+                aq::Kind::ContentSearch => self.loading_listing = true,
                 aq::Kind::FsOperation => {}
             }
         }
+        // Content search matches stream in while the search runs
+        if let Some(rx) = self.grep_rx.as_mut() {
+            let was_empty = self.results.is_empty();
+            while let Ok(node) = rx.try_recv() {
+                self.results.push(node);
+            }
+            if was_empty && !self.results.is_empty() {
+                self.update_focused();
+                self.update_preview();
+            }
+        }
         let completed = self.async_queue.check_tasks().await;
         let mut output = String::new();
         for item in completed {
@@ -3742,10 +10288,20 @@ impl<'a> App<'a> {
                     self.listing = item.res.data_listing.unwrap(); // This should be safe to unwrap
                     self.update_results();
                 }
+                // Name matches which finish after a content search started are stale
+                aq::Kind::ListingResult if self.grep_query().is_some() => {}
                 aq::Kind::ListingResult => {
                     self.results = item.res.data_listing.unwrap(); // This should be safe to unwrap
                     // TODO: Should make a "reset_focus" function
                     self.focus_index = 0;
+                    // Focus an entry which was just created once it shows up
+                    let found = self.focus_target.as_ref().and_then(|target| {
+                        self.results.iter().position(|node| &node.name == target)
+                    });
+                    if let Some(i) = found {
+                        self.focus_index = i;
+                        self.focus_target = None;
+                    }
                     self.update_focused();
                     self.update_preview();
                 }
@@ -3755,7 +10311,7 @@ impl<'a> App<'a> {
                     self.preview_content = Default::default();
                     self.preview_content = self.pretty_metadata(&meta);
                     self.preview_content += Line::styled(SEP, Style::default().fg(self.cs.dim));
-                    let pretty_listing = self.pretty_dir_list(&data);
+                    let pretty_listing = self.pretty_dir_list(&data, None);
                     for line in pretty_listing.lines.iter().take(20) {
                         self.preview_content += Line::from(line.clone());
                     }
@@ -3778,7 +10334,24 @@ impl<'a> App<'a> {
                         self.preview_content += Line::from(line.clone());
                     }
                 }
+                aq::Kind::ContentSearch => {
+                    if let Some(data) = item.res.data_str {
+                        log!("Content search finished: {}", data);
+                    }
+                    if self.results.is_empty() {
+                        self.update_focused();
+                        self.update_preview();
+                    }
+                }
                 aq::Kind::FsOperation => {
+                    if let Some(ops) = item.res.data_ops {
+                        for op in ops {
+                            self.journal.record(op);
+                        }
+                    }
+                    if let Some(replayed) = item.res.data_replayed {
+                        self.journal.finish(replayed);
+                    }
                     if item.res.data_str.is_some() {
                         let data = match &item.res.data_str {
                             Some(d) => d.clone(),
@@ -3829,11 +10402,21 @@ impl<'a> App<'a> {
                     code, modifiers, ..
                 }) = event::read()?
                 {
+                    // Prompt input handling
+                    if self.prompt.is_some() {
+                        self.input_prompt(modifiers, code);
+                        continue;
+                    }
                     // Output window input handling
                     if self.show_output_window {
                         self.input_out_window(modifiers, code);
                         continue;
                     }
+                    // Jobs window input handling
+                    if self.show_jobs_window {
+                        self.input_jobs_window(modifiers, code);
+                        continue;
+                    }
                     // Command window input handling
                     if self.show_command_window {
                         let lr = self.input_cmd_window(modifiers, code);
@@ -3939,36 +10522,84 @@ impl<'a> App<'a> {
         if self.results.is_empty() {
             input_color = self.cs.error;
         }
-        let input_span: Span =
-            Span::styled(format!("{}", input_str), Style::default().fg(input_color));
+        if self.matcher_key.is_some() && self.matcher.is_none() {
+            input_color = self.cs.error;
+        }
+        // Filter tokens which can't be used are marked as errors
+        let mut input_line = Line::default();
+        if self.search_buf.is_empty() || self.grep_query().is_some() {
+            input_line.push_span(Span::styled(input_str, Style::default().fg(input_color)));
+        } else {
+            for token in input_str.split_inclusive(' ') {
+                let color = if query::is_invalid(token.trim_end()) {
+                    self.cs.error
+                } else {
+                    input_color
+                };
+                input_line.push_span(Span::styled(token.to_string(), Style::default().fg(color)));
+            }
+        }
         let suffix: Span = Span::styled(
             format!("|{} ", nf::MAG),
             Style::default().fg(self.cs.search_border),
         );
-        let mut input_line = Line::from(input_span);
         input_line.push_span(suffix);
         let input_widget = Paragraph::new(input_line).block(
             Block::default()
-                .title(format!(
-                    "┤{})))  [ {} / {} ]",
-                    APP_NAME.to_uppercase(),
-                    self.results.len(),
-                    self.listing.len(),
-                ))
+                .title(match self.grep_query() {
+                    Some(_) => format!(
+                        "┤{})))  [ {} matches ]",
+                        APP_NAME.to_uppercase(),
+                        self.results.len(),
+                    ),
+                    None => format!(
+                        "┤{})))  [ {} / {} ]  [ {} ]",
+                        APP_NAME.to_uppercase(),
+                        self.results.len(),
+                        self.listing.len(),
+                        self.cfg.match_mode.as_str(),
+                    ),
+                })
                 .borders(Borders::ALL)
                 .border_style(Style::default().fg(self.cs.search_border)),
         );
 
         // Results list
-        let mut results_pretty = self.pretty_dir_list(&self.results);
+        // Highlights are only worked out for names not seen with this matcher yet
+        let highlight = self.grep_query().is_none();
+        // Names from other directories pile up while the search stays the same
+        if self.match_hits.len() > MAX_MATCH_HITS {
+            self.match_hits.clear();
+        }
+        if let Some(matcher) = self.matcher.as_ref().filter(|_| highlight) {
+            for item in self.results.iter().take(self.cfg.list_limit as usize) {
+                let name = matching::relative(&item.name, &self.cwd);
+                if !self.match_hits.contains_key(name) {
+                    self.match_hits
+                        .insert(name.to_string(), matcher.indices(name));
+                }
+            }
+        }
+        let hits = highlight.then_some(&self.match_hits);
+        let mut results_pretty = self.pretty_dir_list(&self.results, hits);
         if let Some(line) = results_pretty.lines.get_mut(self.focus_index as usize) {
             let sel_span = Span::styled(
                 format!("{}", nf::SEL),
                 Style::default().fg(self.cs.hi).bg(Color::Black),
             );
-            let line_span = Span::styled(format!(" {}", line), Style::default().fg(self.cs.hi));
             let mut new_line = Line::from(sel_span);
-            new_line.push_span(line_span);
+            new_line.push_span(Span::styled(" ", Style::default().fg(self.cs.hi)));
+            // Keep the match highlights on the focused line
+            for span in line.spans.iter() {
+                if span.style.fg == Some(self.cs.matched) {
+                    new_line.push_span(span.clone());
+                } else {
+                    new_line.push_span(Span::styled(
+                        span.content.clone(),
+                        Style::default().fg(self.cs.hi),
+                    ));
+                }
+            }
             *line = new_line;
         }
         let explode_str = if self.mode_explode {
@@ -3986,10 +10617,20 @@ impl<'a> App<'a> {
         } else {
             "".to_string()
         };
+        let sort_str = format!(
+            "[{}{}]",
+            if self.cfg.sort.dirs_first {
+                format!("{} ", nf::DIR)
+            } else {
+                "".to_string()
+            },
+            self.cfg.sort.as_string()
+        );
         let list_title = format!(
-            "|{}{}{} {}",
+            "|{}{}{}{} {}",
             explode_str,
             hidden_str,
+            sort_str,
             util::fpath(&self.cwd),
             loading_str_listing
         );
@@ -4004,6 +10645,28 @@ impl<'a> App<'a> {
             state.select(Some(self.focus_index as usize));
         }
 
+        // Tab bar, only shown with more than one tab
+        let tab_height = if self.tabs.len() > 1 { 1 } else { 0 };
+        let mut tab_line = Line::default();
+        for (i, tab) in self.tabs.iter().enumerate() {
+            let cwd = if i == self.tab_index {
+                &self.cwd
+            } else {
+                &tab.cwd
+            };
+            let name = cwd
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or(cwd.to_string_lossy().to_string());
+            let style = if i == self.tab_index {
+                Style::default().fg(Color::Black).bg(self.cs.search_border)
+            } else {
+                Style::default().fg(self.cs.dim)
+            };
+            tab_line.push_span(Span::styled(format!(" {}:{} ", i + 1, name), style));
+        }
+        let tab_widget = Paragraph::new(tab_line);
+
         // Preview box
         let loading_str_preview = if self.loading_preview {
             loading_arr[loading_index].to_string()
@@ -4035,18 +10698,20 @@ impl<'a> App<'a> {
                 .direction(Direction::Vertical)
                 .constraints(
                     [
-                        Constraint::Length(3), // Input
-                        Constraint::Min(5),    // Results
-                        Constraint::Min(10),   // Preview
+                        Constraint::Length(tab_height), // Tabs
+                        Constraint::Length(3),          // Input
+                        Constraint::Min(5),             // Results
+                        Constraint::Min(10),            // Preview
                     ]
                     .as_ref(),
                 )
                 .split(main_area);
 
-            frame.render_widget(input_widget, vertical_chunks[0]);
-            frame.render_stateful_widget(list_widget, vertical_chunks[1], &mut state);
-            frame.render_widget(preview_widget, vertical_chunks[2]);
-            self.lay_preview_area = vertical_chunks[2];
+            frame.render_widget(tab_widget, vertical_chunks[0]);
+            frame.render_widget(input_widget, vertical_chunks[1]);
+            frame.render_stateful_widget(list_widget, vertical_chunks[2], &mut state);
+            frame.render_widget(preview_widget, vertical_chunks[3]);
+            self.lay_preview_area = vertical_chunks[3];
         } else {
             // Horizontal layout
             let horizontal_chunks = Layout::default()
@@ -4057,11 +10722,19 @@ impl<'a> App<'a> {
             let left_vertical_chunks = Layout::default()
                 .direction(Direction::Vertical)
                 .margin(0)
-                .constraints([Constraint::Length(3), Constraint::Min(1)].as_ref())
+                .constraints(
+                    [
+                        Constraint::Length(tab_height),
+                        Constraint::Length(3),
+                        Constraint::Min(1),
+                    ]
+                    .as_ref(),
+                )
                 .split(horizontal_chunks[0]);
 
-            frame.render_widget(input_widget, left_vertical_chunks[0]);
-            frame.render_stateful_widget(list_widget, left_vertical_chunks[1], &mut state);
+            frame.render_widget(tab_widget, left_vertical_chunks[0]);
+            frame.render_widget(input_widget, left_vertical_chunks[1]);
+            frame.render_stateful_widget(list_widget, left_vertical_chunks[2], &mut state);
             frame.render_widget(preview_widget, horizontal_chunks[1]);
             self.lay_preview_area = horizontal_chunks[1];
         }
@@ -4082,7 +10755,7 @@ impl<'a> App<'a> {
             unix_time % 60
         );
         let multi_count = self.multi_selection.len();
-        let status_text = format!(
+        let mut status_text = format!(
             " {} {} | {} {} | {} {} | {}",
             loading_str_status,
             self.async_queue.pending_count(),
@@ -4092,6 +10765,23 @@ impl<'a> App<'a> {
             multi_count,
             hhmmss
         );
+        // Combined progress of running file operations
+        if let Some(progress) = self.async_queue.progress() {
+            status_text += " |";
+            if let Some(percent) = progress.percent() {
+                status_text += &format!(" {} {}%", util::progress_bar(percent, 10), percent);
+            }
+            if progress.bytes_total > 0 {
+                status_text += &format!(
+                    " {}/{}",
+                    util::human_size(progress.bytes_done),
+                    util::human_size(progress.bytes_total)
+                );
+            } else {
+                // Nothing totalled, e.g. trashing
+                status_text += &format!(" {} files", progress.files_done);
+            }
+        }
         let status_widget =
             Paragraph::new(status_text).style(Style::default().fg(self.cs.misc).bg(Color::Black));
         frame.render_widget(status_widget, status_area);
@@ -4139,6 +10829,107 @@ impl<'a> App<'a> {
                 );
             frame.render_widget(command_paragraph, popup_area);
         }
+        // --- Jobs Window ---
+        if self.show_jobs_window {
+            let mut text = Text::default();
+            let pending = self.async_queue.get_pending();
+            let finished = self.async_queue.get_finished();
+            if pending.is_empty() && finished.is_empty() {
+                text += Line::styled("No jobs.", Style::default().fg(self.cs.dim));
+            }
+            let job_line = |row: usize, line: String, color: Color| {
+                if row == self.jobs_index {
+                    Line::styled(
+                        format!("{} {}", nf::SEL, line),
+                        Style::default().fg(self.cs.hi),
+                    )
+                } else {
+                    Line::styled(format!("  {}", line), Style::default().fg(color))
+                }
+            };
+            for (row, item) in pending.iter().enumerate() {
+                let state = if item.is_paused() {
+                    "paused"
+                } else {
+                    "running"
+                };
+                text += job_line(
+                    row,
+                    format!(
+                        "#{} {:?} {} [{} {}]",
+                        item.id,
+                        item.kind,
+                        item.label,
+                        util::human_duration(item.elapsed()),
+                        state
+                    ),
+                    self.cs.header,
+                );
+                if let Some(progress) = item.progress() {
+                    let mut line = String::from("   ");
+                    if let Some(percent) = progress.percent() {
+                        line += &format!(" {} {}%", util::progress_bar(percent, 20), percent);
+                    }
+                    if progress.bytes_total > 0 {
+                        line += &format!(
+                            " {}/{}",
+                            util::human_size(progress.bytes_done),
+                            util::human_size(progress.bytes_total)
+                        );
+                    }
+                    if progress.files_total > 0 {
+                        line += &format!(" {}/{} files", progress.files_done, progress.files_total);
+                    } else {
+                        line += &format!(" {} files", progress.files_done);
+                    }
+                    text += Line::styled(line, Style::default().fg(self.cs.info));
+                    if !progress.current.is_empty() {
+                        text += Line::styled(
+                            format!("    {}", progress.current),
+                            Style::default().fg(self.cs.dim),
+                        );
+                    }
+                }
+            }
+            // Newest first
+            for (i, job) in finished.iter().rev().enumerate() {
+                let (state, color) = match job.rc {
+                    Some(0) => ("rc:0".to_string(), self.cs.ok),
+                    Some(rc) => (format!("rc:{}", rc), self.cs.error),
+                    None => ("cancelled".to_string(), self.cs.warning),
+                };
+                text += job_line(
+                    pending.len() + i,
+                    format!(
+                        "#{} {:?} {} [{} {}]",
+                        job.id,
+                        job.kind,
+                        job.label,
+                        util::human_duration(job.elapsed),
+                        state
+                    ),
+                    color,
+                );
+            }
+            text += Line::from("");
+            text += Line::styled(
+                "[c] cancel  [p] pause/resume  [r] retry  [x] clear finished",
+                Style::default().fg(self.cs.tip),
+            );
+            let popup_area = centered_rect(popup_width, 90, area);
+            frame.render_widget(Clear, popup_area);
+            let jobs_paragraph = Paragraph::new(text)
+                .style(Style::default().bg(Color::Black))
+                .block(
+                    Block::default()
+                        .title(format!("{} Jobs ('esc' to exit)", nf::WAIT))
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(Color::Magenta))
+                        .style(Style::default().bg(Color::Black)),
+                )
+                .wrap(Wrap { trim: false });
+            frame.render_widget(jobs_paragraph, popup_area);
+        }
         // --- Output Window ---
         if self.show_output_window {
             let popup_area = centered_rect(popup_width, 90, area);
@@ -4156,6 +10947,41 @@ impl<'a> App<'a> {
                 .scroll((self.scroll_off_output as u16, self.scroll_off_output as u16));
             frame.render_widget(command_paragraph, popup_area);
         }
+        // --- Prompt ---
+        if let Some(prompt) = &self.prompt {
+            let mut text = Text::default();
+            for line in prompt.text.lines() {
+                text += Line::styled(line.to_string(), Style::default().fg(self.cs.misc));
+            }
+            text += Line::from("");
+            let options = prompt
+                .options
+                .iter()
+                .map(|(key, label)| format!("[{}] {}", key, label))
+                .collect::<Vec<String>>()
+                .join("  ");
+            text += Line::styled(
+                format!("{}  [esc] cancel", options),
+                Style::default().fg(self.cs.tip),
+            );
+            let popup_area = centered_rect(
+                popup_width,
+                lines_to_percent(text.lines.len() + 4).max(20),
+                area,
+            );
+            frame.render_widget(Clear, popup_area);
+            let prompt_paragraph = Paragraph::new(text)
+                .style(Style::default().bg(Color::Black))
+                .block(
+                    Block::default()
+                        .title(format!("{} {}", nf::WARN, prompt.title))
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(self.cs.warning))
+                        .style(Style::default().bg(Color::Black)),
+                )
+                .wrap(Wrap { trim: false });
+            frame.render_widget(prompt_paragraph, popup_area);
+        }
     }
 }
 